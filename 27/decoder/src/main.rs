@@ -10,6 +10,11 @@ fn main() {
 
     if let Err(e) = run(&cfg) {
         eprintln!("Application error: {}", e);
+        let mut source = e.source();
+        while let Some(cause) = source {
+            eprintln!("  caused by: {}", cause);
+            source = cause.source();
+        }
         std::process::exit(1);
     }
 }
@@ -1,3 +1,5 @@
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
 use std::convert::Into;
 use std::convert::TryFrom;
 use std::convert::TryInto;
@@ -5,33 +7,242 @@ use std::error::Error;
 use std::fmt;
 use std::io::Read;
 use std::io::Seek;
+use std::io::Write;
 use std::str;
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 use prettytable::{Row, Table};
 
+mod adpcm;
+mod aiff;
+mod bext;
+mod flac;
+mod lint;
+mod tree;
+mod writer;
+
+// The data size for a stream whose header was never filled in
+const UNTIL_EOF: u64 = u64::MAX;
+
+// Roughly 3000 on a 16 bit scale, which was what worked on the test file
+const DEFAULT_THRESHOLD: f32 = 0.09;
+
+// Which of the channels in the file the Morse is decoded from
+#[derive(PartialEq, Debug)]
+enum ChannelSelection {
+    // Counting from 0
+    Single(usize),
+    // The average of every channel in each frame
+    Mix,
+}
+
+impl Default for ChannelSelection {
+    fn default() -> Self {
+        ChannelSelection::Single(0)
+    }
+}
+
+impl ChannelSelection {
+    fn pick(&self, frame: &[f32]) -> f32 {
+        match self {
+            ChannelSelection::Single(channel) => frame[*channel],
+            ChannelSelection::Mix => frame.iter().sum::<f32>() / frame.len() as f32,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Default)]
+enum Command {
+    #[default]
+    Decode,
+    // Check the file for problems rather than decoding it
+    Lint,
+    // Show how the chunks in the file are laid out
+    Chunks,
+}
+
+#[derive(PartialEq, Debug, Default)]
+enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+// How samples are laid out in input that has no header to say
+#[derive(PartialEq, Debug)]
+struct RawFormat {
+    sample_rate: u32,
+    channels: u16,
+    bits_per_sample: u16,
+    signedness: Signedness,
+    endianness: Endianness,
+}
+
+impl Default for RawFormat {
+    fn default() -> Self {
+        RawFormat {
+            sample_rate: 8000,
+            channels: 1,
+            bits_per_sample: 16,
+            signedness: Signedness::default(),
+            endianness: Endianness::default(),
+        }
+    }
+}
+
+impl RawFormat {
+    // The block align and byte rate worked out from these have to fit in the header's u16
+    // and u32, same as they would in a WAV file
+    fn is_valid(&self) -> bool {
+        if self.sample_rate == 0
+            || self.channels == 0
+            || ![8, 16, 24, 32].contains(&self.bits_per_sample)
+        {
+            return false;
+        }
+        self.channels
+            .checked_mul(self.bits_per_sample / 8)
+            .and_then(|block_align| self.sample_rate.checked_mul(block_align.into()))
+            .is_some()
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub struct Config<'a> {
     fname: &'a String,
+    channel: ChannelSelection,
+    command: Command,
+    output: OutputFormat,
+    // Work out sizes from the file rather than trusting the header
+    lenient: bool,
+    // Where to write a repaired copy of the file, which is then what gets decoded
+    repair: Option<&'a String>,
+    // Headerless PCM rather than a WAV file
+    raw: Option<RawFormat>,
+    // Print each letter with when it was sent rather than just the text
+    timestamps: bool,
+    // Where to write a copy of the file with a label at the start of each decoded word
+    labels: Option<&'a String>,
+    // Where to write the samples out again as plain PCM, which is then what gets decoded
+    convert: Option<&'a String>,
+    // Convert to 32 bit float rather than keeping the sample size
+    float: bool,
 }
 
 #[derive(PartialEq, Debug)]
 pub enum ConfigParseError {
     NotEnoughArgs,
     TooManyArgs,
+    InvalidChannel,
+    InvalidRawFormat,
+    LabelsNeedWav,
+    RepairOrConvert,
+}
+
+fn next_number<'a, I, T>(args: &mut I, invalid: ConfigParseError) -> Result<T, ConfigParseError>
+where
+    I: Iterator<Item = &'a String>,
+    T: str::FromStr,
+{
+    let n = args.next().ok_or(ConfigParseError::NotEnoughArgs)?;
+    n.parse().map_err(|_| invalid)
 }
 
+// Usage: decoder [--channel N | --mono] [--lenient] [--repair OUT] [--timestamps]
+//                [--labels OUT] <file or - for stdin>
+//        decoder [--mono] [--lenient] --convert OUT [--float] <file or - for stdin>
+//        decoder --raw [--rate HZ] [--channels N] [--bits 8|16|24|32] [--signed | --unsigned]
+//                [--big-endian] [--channel N | --mono] <file or - for stdin>
+//        decoder lint [--json] <file or - for stdin>
+//        decoder chunks <file or - for stdin>
 impl<'a> std::convert::TryFrom<&'a [String]> for Config<'a> {
     type Error = ConfigParseError;
     fn try_from(args: &'a [String]) -> Result<Self, Self::Error> {
-        if args.len() < 2 {
-            return Err(ConfigParseError::NotEnoughArgs);
+        let mut fname = None;
+        let mut channel = ChannelSelection::default();
+        let mut command = Command::default();
+        let mut output = OutputFormat::default();
+        let mut lenient = false;
+        let mut repair = None;
+        let mut raw = false;
+        let mut raw_format = RawFormat::default();
+        let mut timestamps = false;
+        let mut labels = None;
+        let mut convert = None;
+        let mut float = false;
+        let mut args_iter = args.iter().skip(1).peekable();
+        match args_iter.peek().map(|arg| arg.as_str()) {
+            Some("lint") => command = Command::Lint,
+            Some("chunks") => command = Command::Chunks,
+            _ => {}
+        }
+        if command != Command::Decode {
+            args_iter.next();
+        }
+        while let Some(arg) = args_iter.next() {
+            match arg.as_str() {
+                "--json" => output = OutputFormat::Json,
+                "--channel" => {
+                    let n = next_number(&mut args_iter, ConfigParseError::InvalidChannel)?;
+                    channel = ChannelSelection::Single(n);
+                }
+                "--mono" => channel = ChannelSelection::Mix,
+                "--lenient" => lenient = true,
+                "--repair" => {
+                    repair = Some(args_iter.next().ok_or(ConfigParseError::NotEnoughArgs)?);
+                }
+                "--raw" => raw = true,
+                "--rate" => {
+                    raw_format.sample_rate =
+                        next_number(&mut args_iter, ConfigParseError::InvalidRawFormat)?;
+                }
+                "--channels" => {
+                    raw_format.channels =
+                        next_number(&mut args_iter, ConfigParseError::InvalidRawFormat)?;
+                }
+                "--bits" => {
+                    raw_format.bits_per_sample =
+                        next_number(&mut args_iter, ConfigParseError::InvalidRawFormat)?;
+                }
+                "--signed" => raw_format.signedness = Signedness::Signed,
+                "--unsigned" => raw_format.signedness = Signedness::Unsigned,
+                "--big-endian" => raw_format.endianness = Endianness::Big,
+                "--timestamps" => timestamps = true,
+                "--labels" => {
+                    labels = Some(args_iter.next().ok_or(ConfigParseError::NotEnoughArgs)?);
+                }
+                "--convert" => {
+                    convert = Some(args_iter.next().ok_or(ConfigParseError::NotEnoughArgs)?);
+                }
+                "--float" => float = true,
+                _ if fname.is_none() => fname = Some(arg),
+                _ => return Err(ConfigParseError::TooManyArgs),
+            }
         }
-        if args.len() > 2 {
-            return Err(ConfigParseError::TooManyArgs);
+
+        if raw && !raw_format.is_valid() {
+            return Err(ConfigParseError::InvalidRawFormat);
+        }
+        if raw && labels.is_some() {
+            return Err(ConfigParseError::LabelsNeedWav);
+        }
+        if repair.is_some() && convert.is_some() {
+            return Err(ConfigParseError::RepairOrConvert);
         }
 
-        Ok(Config { fname: &args[1] })
+        Ok(Config {
+            fname: fname.ok_or(ConfigParseError::NotEnoughArgs)?,
+            channel,
+            command,
+            output,
+            lenient,
+            repair,
+            raw: raw.then_some(raw_format),
+            timestamps,
+            labels,
+            convert,
+            float,
+        })
     }
 }
 
@@ -47,19 +258,277 @@ impl fmt::Display for ConfigParseError {
         let s = match self {
             ConfigParseError::TooManyArgs => "Too many arguments passed",
             ConfigParseError::NotEnoughArgs => "Not enough args passed",
+            ConfigParseError::InvalidChannel => "The channel should be a number, counting from 0",
+            ConfigParseError::InvalidRawFormat => {
+                "Raw input needs a sample rate and channel count above 0, and 8, 16, 24 or 32 bits, \
+                 and no more bytes a frame or a second than a WAV header can hold"
+            }
+            ConfigParseError::LabelsNeedWav => {
+                "Labels can only be added to WAV files, not raw input"
+            }
+            ConfigParseError::RepairOrConvert => {
+                "Only one of --repair and --convert can be given, as each makes the copy that's decoded"
+            }
         };
         write!(f, "{}", s)
     }
 }
 
 pub fn run(cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let mut f = std::fs::File::open(cfg.fname)?;
-    let wh = WaveFile::try_from(&mut f)?;
-    println!("{}", wh);
-    println!("{}", parse(&wh.ldata, 3000));
+    match cfg.command {
+        Command::Lint => return lint_and_print(cfg),
+        Command::Chunks => return Ok(chunks_and_print(cfg)?),
+        Command::Decode => {}
+    }
+    let copy = match (cfg.repair, cfg.convert) {
+        (Some(out), _) => {
+            repair(cfg.fname, out)?;
+            Some(out)
+        }
+        (None, Some(out)) => {
+            convert(cfg, out)?;
+            Some(out)
+        }
+        (None, None) => None,
+    };
+    if let Some(out) = copy {
+        let mut f = std::fs::File::open(out)?;
+        if let Some(labels) = cfg.labels {
+            return label_and_print(&mut f, cfg, labels);
+        }
+        let header = WaveHeader::try_from(&mut f)?;
+        decode_and_print(&header, &mut f, cfg)?;
+        return Ok(());
+    }
+
+    if let Some(labels) = cfg.labels {
+        return if cfg.fname == "-" {
+            label_and_print(&mut read_stdin()?, cfg, labels)
+        } else {
+            label_and_print(&mut std::fs::File::open(cfg.fname)?, cfg, labels)
+        };
+    }
+
+    if cfg.fname == "-" {
+        let (is_flac, mut stdin) = flac::peek_magic(std::io::stdin().lock())?;
+        if cfg.raw.is_none() && is_flac {
+            return Ok(decode_flac(stdin, cfg)?);
+        }
+        let header = match (&cfg.raw, cfg.lenient) {
+            (Some(raw), _) => WaveHeader::from_raw(raw, UNTIL_EOF),
+            (None, true) => WaveHeader::recover_stream(&mut stdin)?,
+            (None, false) => WaveHeader::from_stream(&mut stdin)?,
+        };
+        decode_and_print(&header, &mut stdin, cfg)?;
+    } else {
+        let mut f = std::fs::File::open(cfg.fname)?;
+        if cfg.raw.is_none() && flac::has_magic(&mut f)? {
+            return Ok(decode_flac(f, cfg)?);
+        }
+        let header = match (&cfg.raw, cfg.lenient) {
+            (Some(raw), _) => WaveHeader::from_raw(raw, f.metadata()?.len()),
+            (None, true) => WaveHeader::recover(&mut f)?,
+            (None, false) => WaveHeader::try_from(&mut f)?,
+        };
+        decode_and_print(&header, &mut f, cfg)?;
+    }
+    Ok(())
+}
+
+// FLAC has no sizes to get wrong, so there's nothing for lenient reading to do
+fn decode_flac<R: Read>(f: R, cfg: &Config) -> Result<(), WavReadError> {
+    let (header, mut samples) = flac::open(f)?;
+    decode_and_print(&header, &mut samples, cfg)?;
+    Ok(())
+}
+
+// The labels are written to a copy, the file that was decoded is left as it was
+fn label_and_print<R: Read + Seek>(
+    f: &mut R,
+    cfg: &Config,
+    out: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let header = match cfg.lenient {
+        true => WaveHeader::recover(f)?,
+        false => WaveHeader::from_reader(f)?,
+    };
+    let letters = decode_and_print(&header, f, cfg)?;
+    let mut w = std::io::BufWriter::new(std::fs::File::create(out)?);
+    header.write_labelled(f, &word_labels(&letters), &mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+// For when the whole file is needed to check sizes against its length
+fn read_stdin() -> std::io::Result<std::io::Cursor<Vec<u8>>> {
+    let mut bytes = Vec::new();
+    std::io::stdin().read_to_end(&mut bytes)?;
+    Ok(std::io::Cursor::new(bytes))
+}
+
+fn repair(fname: &str, out: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if fname == "-" {
+        repair_to(&mut read_stdin()?, out)
+    } else {
+        repair_to(&mut std::fs::File::open(fname)?, out)
+    }
+}
+
+// The chunks are copied rather than decoded and written out again, so the samples, and
+// anything else in the file, come out exactly as they went in
+fn repair_to<R: Read + Seek>(f: &mut R, out: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let header = WaveHeader::recover(f)?;
+    for warning in &header.warnings {
+        warn(warning);
+    }
+    let mut w = std::io::BufWriter::new(std::fs::File::create(out)?);
+    header.write_repaired(f, &mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+// The samples are written out again rather than the chunks copied, so anything that can be
+// read, such as ADPCM, AIFF or FLAC, comes out as a WAV file that other tools can open
+fn convert(cfg: &Config, out: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let wave = if cfg.fname == "-" {
+        read_wave_file(&mut read_stdin()?, cfg)?
+    } else {
+        read_wave_file(&mut std::fs::File::open(cfg.fname)?, cfg)?
+    };
+    for warning in &wave.header.warnings {
+        warn(warning);
+    }
+    let mut w = std::io::BufWriter::new(std::fs::File::create(out)?);
+    wave.converted(&cfg.channel, cfg.float)?.write(&mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+// Anything the decoder can read, decoded into memory
+fn read_wave_file<R: Read + Seek>(f: &mut R, cfg: &Config) -> Result<WaveFile, WavReadError> {
+    if cfg.raw.is_none() && flac::has_magic(f)? {
+        let (header, mut samples) = flac::open(f.by_ref())?;
+        let mut rc = WaveFile::from(header);
+        rc.read_samples(&mut samples)?;
+        return Ok(rc);
+    }
+    match (&cfg.raw, cfg.lenient) {
+        (Some(raw), _) => {
+            let file_len = f.seek(std::io::SeekFrom::End(0))?;
+            f.seek(std::io::SeekFrom::Start(0))?;
+            let mut rc = WaveFile::from(WaveHeader::from_raw(raw, file_len));
+            rc.read_samples(f)?;
+            Ok(rc)
+        }
+        (None, true) => WaveFile::recover(f),
+        (None, false) => WaveFile::from_reader(f),
+    }
+}
+
+fn chunks_and_print(cfg: &Config) -> Result<(), WavReadError> {
+    if cfg.fname == "-" {
+        print_chunks(&mut read_stdin()?)
+    } else {
+        print_chunks(&mut std::fs::File::open(cfg.fname)?)
+    }
+}
+
+// The tree comes first as it can still be shown when the header can't be decoded
+fn print_chunks<R: Read + Seek>(f: &mut R) -> Result<(), WavReadError> {
+    println!("{}", tree::read_tree(f)?);
+    f.seek(std::io::SeekFrom::Start(0))?;
+    println!("{}", WaveHeader::from_reader(f)?);
     Ok(())
 }
 
+fn lint_and_print(cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let report = if cfg.fname == "-" {
+        lint::lint(&mut read_stdin()?)?
+    } else {
+        lint::lint(&mut std::fs::File::open(cfg.fname)?)?
+    };
+    match cfg.output {
+        OutputFormat::Table => println!("{}", report),
+        OutputFormat::Json => println!("{}", report.to_json()),
+    }
+    match report.errors() {
+        0 => Ok(()),
+        errors => Err(Box::new(lint::LintFailed(errors))),
+    }
+}
+
+// The samples are decoded as they're read rather than loaded up front, so
+// hours long recordings don't need to fit in memory. Gives back the letters for anything
+// that wants to do more with them
+fn decode_and_print<R: Read>(
+    header: &WaveHeader,
+    f: &mut R,
+    cfg: &Config,
+) -> Result<Vec<Letter>, WavReadError> {
+    println!("{}", header);
+    for warning in &header.warnings {
+        warn(warning);
+    }
+    let letters = decode_letters(header, f, &cfg.channel)?;
+    if cfg.timestamps {
+        if let Some(tag) = header.source_tag() {
+            println!("[{}]", tag);
+        }
+        // The times already show where the gaps between words are
+        for letter in letters.iter().filter(|letter| letter.text != " ") {
+            println!("{} {}", header.timestamp(letter.sample), letter.text);
+        }
+        return Ok(letters);
+    }
+
+    let text = text_of(&letters);
+    match header.source_tag() {
+        Some(tag) => println!("[{}] {}", tag, text),
+        None => println!("{}", text),
+    }
+    Ok(letters)
+}
+
+fn decode_letters<R: Read>(
+    header: &WaveHeader,
+    f: &mut R,
+    channel: &ChannelSelection,
+) -> Result<Vec<Letter>, WavReadError> {
+    if let ChannelSelection::Single(n) = channel {
+        if *n >= usize::from(header.num_channels) {
+            return Err(WavReadError::NoSuchChannel);
+        }
+    }
+
+    let mut error = None;
+    let mut frames = Frames::new(header, f)?;
+    let samples = frames.by_ref().map_while(|frame| match frame {
+        Ok(frame) => Some(channel.pick(&frame)),
+        Err(e) => {
+            error = Some(e);
+            None
+        }
+    });
+    let timing = Timing::from_rate(header.sample_rate.0);
+    let letters = parse_letters(samples, DEFAULT_THRESHOLD, timing);
+    if let Some(e) = error {
+        return Err(e);
+    }
+    // A stream can't be measured up front, so this is only found out at the end
+    let missing = frames.missing();
+    if missing > 0 && header.data_size != UNTIL_EOF {
+        warn(&format!(
+            "The data chunk should be {} bytes but {} of them never arrived",
+            header.data_size, missing
+        ));
+    }
+    Ok(letters)
+}
+
+fn warn(msg: &str) {
+    eprintln!("Warning: {}", msg);
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 enum Symbol {
     Short,
@@ -67,21 +536,17 @@ enum Symbol {
     Space,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Default)]
 enum WaveFormat {
     Pcm,
+    #[default]
     Float,
     Alaw,
     Mulaw,
+    ImaAdpcm,
     Extensible,
 }
 
-impl Default for WaveFormat {
-    fn default() -> Self {
-        WaveFormat::Float
-    }
-}
-
 impl fmt::Display for WaveFormat {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s: &'static str = match self {
@@ -89,6 +554,7 @@ impl fmt::Display for WaveFormat {
             WaveFormat::Pcm => "PCM",
             WaveFormat::Float => "Float",
             WaveFormat::Mulaw => "MULAW",
+            WaveFormat::ImaAdpcm => "IMA ADPCM",
             WaveFormat::Extensible => "Extensible",
         };
         write!(f, "{}", s)
@@ -100,18 +566,19 @@ impl std::convert::TryFrom<u16> for WaveFormat {
     fn try_from(value: u16) -> Result<Self, Self::Error> {
         match value {
             1 => Ok(WaveFormat::Pcm),
-            _ => Err(WavReadError::Header(WavHeaderError::UnrecognisedWaveFormat)),
+            3 => Ok(WaveFormat::Float),
+            6 => Ok(WaveFormat::Alaw),
+            7 => Ok(WaveFormat::Mulaw),
+            0x11 => Ok(WaveFormat::ImaAdpcm),
+            0xfffe => Ok(WaveFormat::Extensible),
+            // The caller has the bytes it came from, in whichever order the file uses
+            _ => Err(WavHeaderError::UnrecognisedWaveFormat.into()),
         }
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Default)]
 struct Hertz(u32);
-impl Default for Hertz {
-    fn default() -> Self {
-        Hertz(0)
-    }
-}
 
 impl fmt::Display for Hertz {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -126,47 +593,141 @@ enum WavHeaderError {
     Wave,
     Format,
     FormatLength,
+    Data,
     UnrecognisedWaveFormat,
+    BitsPerSample,
+    BlockAlign,
 }
 
 impl std::convert::From<&WavHeaderError> for &'static str {
     fn from(whe: &WavHeaderError) -> &'static str {
         match whe {
             WavHeaderError::Format => "'fmt ' not present", 
-            WavHeaderError::FormatLength => "Format chunk size expected to be fixed at 16, but this wasn't in the file, or it wasn't in the right place",
+            WavHeaderError::FormatLength => "Format chunk size expected to be 16, or big enough to hold its extension block, but this wasn't in the file, or it wasn't in the right place",
+            WavHeaderError::Data => "'data' not present",
             WavHeaderError::Riff => "'RIFF' wasn't at the start of the file",
             WavHeaderError::Wave => "'WAVE' wasn't in the right place of the header",
-            WavHeaderError::UnrecognisedWaveFormat => "Expected the file to by a PCM format, but it wasn't"
+            WavHeaderError::UnrecognisedWaveFormat => "Expected the file to be a PCM, IEEE float, A-law, mu-law or IMA ADPCM format, but it wasn't",
+            WavHeaderError::BitsPerSample => "The bits per sample isn't supported for the audio format",
+            WavHeaderError::BlockAlign => "The block align doesn't fit a sample for each channel",
         }
     }
 }
-#[derive(Debug, PartialEq)]
+
+// Where in the file something went wrong and what was there, so a broken capture can be
+// looked at in a hex editor. Whatever finds the problem fills in what it knows
+#[derive(Debug, Default)]
+struct ErrorContext {
+    chunk: Option<[u8; 4]>,
+    offset: Option<u64>,
+    found: Vec<u8>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(chunk) = &self.chunk {
+            parts.push(format!("'{}' chunk", String::from_utf8_lossy(chunk)));
+        }
+        if let Some(offset) = self.offset {
+            parts.push(format!("at byte {}", offset));
+        }
+        if !self.found.is_empty() {
+            let hex: Vec<String> = self.found.iter().map(|b| format!("{:02x}", b)).collect();
+            let mut found = format!("found {}", hex.join(" "));
+            // Ids and magic numbers are easier to recognise as text
+            if self
+                .found
+                .iter()
+                .all(|b| b.is_ascii_graphic() || *b == b' ')
+            {
+                found += &format!(" \"{}\"", String::from_utf8_lossy(&self.found));
+            }
+            parts.push(found);
+        }
+        if parts.is_empty() {
+            return Ok(());
+        }
+        write!(f, " ({})", parts.join(", "))
+    }
+}
+
+#[derive(Debug)]
 enum WavReadError {
-    FileIO(std::io::ErrorKind),
+    FileIO(std::io::Error, ErrorContext),
     DataConvertToString(std::str::Utf8Error),
-    Header(WavHeaderError),
+    Header(WavHeaderError, ErrorContext),
+    NoSuchChannel,
+}
+
+impl WavReadError {
+    fn context_mut(&mut self) -> Option<&mut ErrorContext> {
+        match self {
+            WavReadError::FileIO(_, context) | WavReadError::Header(_, context) => Some(context),
+            _ => None,
+        }
+    }
+
+    // The closer to the problem the more exact the location, so anything already known is kept
+    fn at(mut self, offset: u64) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.offset.get_or_insert(offset);
+        }
+        self
+    }
+
+    fn in_chunk(mut self, chunk: &Chunk) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.chunk.get_or_insert(chunk.id);
+        }
+        self.at(chunk.offset)
+    }
+
+    fn found(mut self, bytes: &[u8]) -> Self {
+        if let Some(context) = self.context_mut() {
+            context.found = bytes.to_vec();
+        }
+        self
+    }
 }
 
 impl fmt::Display for WavReadError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let specific: &'static str = match self {
-            WavReadError::FileIO(_) => "FileIO error and unable to read from the file. Ensure it has a valid 'data' subchunk",
-            WavReadError::DataConvertToString(_) => "Error converting Data to string",
-            WavReadError::Header(err) => err.into(),
+        let (specific, context): (&'static str, _) = match self {
+            WavReadError::FileIO(_, context) => (
+                "FileIO error and unable to read from the file",
+                Some(context),
+            ),
+            WavReadError::DataConvertToString(_) => ("Error converting Data to string", None),
+            WavReadError::Header(err, context) => (err.into(), Some(context)),
+            WavReadError::NoSuchChannel => ("The channel to decode isn't in the file", None),
         };
-        write!(f, "Error reading from WAV file: {}", specific)
+        write!(f, "Error reading from WAV file: {}", specific)?;
+        match context {
+            Some(context) => write!(f, "{}", context),
+            None => Ok(()),
+        }
     }
 }
 impl Error for WavReadError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        // Generic error, underlying cause isn't tracked.
-        None
+        match self {
+            WavReadError::FileIO(e, _) => Some(e),
+            WavReadError::DataConvertToString(e) => Some(e),
+            _ => None,
+        }
     }
 }
 
 impl std::convert::From<std::io::Error> for WavReadError {
     fn from(value: std::io::Error) -> Self {
-        WavReadError::FileIO(value.kind())
+        WavReadError::FileIO(value, ErrorContext::default())
+    }
+}
+
+impl std::convert::From<WavHeaderError> for WavReadError {
+    fn from(value: WavHeaderError) -> Self {
+        WavReadError::Header(value, ErrorContext::default())
     }
 }
 
@@ -176,18 +737,189 @@ impl std::convert::From<std::str::Utf8Error> for WavReadError {
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Default)]
 struct RiffHeader {
-    file_size: u32,
+    file_size: u64,
+    container: Container,
 }
 
-impl Default for RiffHeader {
-    fn default() -> Self {
-        RiffHeader { file_size: 0 }
+impl RiffHeader {
+    // How long the file should be. The RIFF size doesn't include the id and itself, but the
+    // Wave64 one does
+    fn declared_len(&self) -> u64 {
+        match self.container {
+            Container::Wave64 => self.file_size,
+            // Saturates, as a size from ds64 can be anything
+            _ => self.file_size.saturating_add(8),
+        }
+    }
+
+    fn set_len(&mut self, len: u64) {
+        self.file_size = match self.container {
+            Container::Wave64 => len,
+            _ => len.saturating_sub(8),
+        };
     }
 }
 
-#[derive(PartialEq, Debug)]
+// The flavours of RIFF that can hold WAVE data. RF64 and BW64 are RIFF with the 64 bit sizes
+// kept in a ds64 chunk, Wave64 uses GUIDs for ids and 64 bit sizes everywhere, and RIFX is
+// RIFF with every number big endian
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
+enum Container {
+    #[default]
+    Riff,
+    Rf64,
+    Bw64,
+    Wave64,
+    Rifx,
+    // Apple's AIFF and AIFF-C, which are laid out like RIFX with their own chunks
+    Aiff,
+    Aifc,
+    // Not chunks at all, the samples are decoded from FLAC frames
+    Flac,
+    // Headerless samples
+    Raw,
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s: &'static str = match self {
+            Container::Riff => "RIFF",
+            Container::Rf64 => "RF64",
+            Container::Bw64 => "BW64",
+            Container::Wave64 => "Wave64",
+            Container::Rifx => "RIFX",
+            Container::Aiff => "AIFF",
+            Container::Aifc => "AIFF-C",
+            Container::Flac => "FLAC",
+            Container::Raw => "Raw",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Container {
+    // The magic, the size and the WAVE id
+    fn file_header_len(self) -> u64 {
+        match self {
+            Container::Wave64 => 16 + 8 + 16,
+            _ => 12,
+        }
+    }
+
+    fn chunk_header_len(self) -> u64 {
+        match self {
+            Container::Wave64 => 16 + 8,
+            _ => 8,
+        }
+    }
+
+    fn alignment(self) -> u64 {
+        match self {
+            Container::Wave64 => 8,
+            _ => 2,
+        }
+    }
+
+    fn endianness(self) -> Endianness {
+        match self {
+            Container::Rifx | Container::Aiff | Container::Aifc => Endianness::Big,
+            _ => Endianness::Little,
+        }
+    }
+
+    fn is_aiff(self) -> bool {
+        self == Container::Aiff || self == Container::Aifc
+    }
+
+    // What comes after the size in the file header
+    fn form_type(self) -> &'static [u8; 4] {
+        match self {
+            Container::Aiff => b"AIFF",
+            Container::Aifc => b"AIFC",
+            _ => b"WAVE",
+        }
+    }
+
+    // The chunks that hold the format and the samples
+    fn format_id(self) -> &'static [u8; 4] {
+        match self {
+            Container::Aiff | Container::Aifc => b"COMM",
+            _ => b"fmt ",
+        }
+    }
+
+    fn data_id(self) -> &'static [u8; 4] {
+        match self {
+            Container::Aiff | Container::Aifc => b"SSND",
+            _ => b"data",
+        }
+    }
+
+    fn has_ds64(self) -> bool {
+        self == Container::Rf64 || self == Container::Bw64
+    }
+}
+
+// Wave64 GUIDs, the first 4 bytes of each of them are the same as the RIFF id
+const WAVE64_RIFF_GUID_TAIL: [u8; 12] = [
+    0x2e, 0x91, 0xcf, 0x11, 0xa5, 0xd6, 0x28, 0xdb, 0x04, 0xc1, 0x00, 0x00,
+];
+const WAVE64_WAVE_GUID: [u8; 16] = [
+    0x77, 0x61, 0x76, 0x65, 0xf3, 0xac, 0xd3, 0x11, 0x8c, 0xd1, 0x00, 0xc0, 0x4f, 0x8e, 0xdb, 0x8a,
+];
+
+// Whether PCM samples are signed. WAV files only use unsigned for 8 bit samples
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
+enum Signedness {
+    #[default]
+    ByWidth,
+    Signed,
+    Unsigned,
+}
+
+impl Signedness {
+    fn is_unsigned(self, width: usize) -> bool {
+        match self {
+            Signedness::ByWidth => width == 1,
+            Signedness::Signed => false,
+            Signedness::Unsigned => true,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
+enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endianness {
+    fn u32_from_bytes(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn u16_to_bytes(self, value: u16) -> [u8; 2] {
+        match self {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        }
+    }
+
+    fn u32_to_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Default)]
 struct WaveHeader {
     riff: RiffHeader,
     sample_rate: Hertz,
@@ -196,126 +928,682 @@ struct WaveHeader {
     byte_rate: Hertz,
     bits_per_sample: u16,
     block_align: u16,
-    data_size: u16,
-}
-
-impl Default for WaveHeader {
-    fn default() -> Self {
-        WaveHeader {
-            riff: RiffHeader::default(),
-            sample_rate: Hertz::default(),
-            audio_format: WaveFormat::default(),
-            bits_per_sample: 0,
-            byte_rate: Hertz::default(),
-            block_align: 0,
-            data_size: 0,
-            num_channels: 0,
-        }
-    }
+    // How many bytes of samples there are to read, normally the length of 'data', and where
+    // in the file they start
+    data_size: u64,
+    data_offset: u64,
+    // Only filled in for WAVE_FORMAT_EXTENSIBLE files
+    valid_bits_per_sample: u16,
+    channel_mask: u32,
+    sub_format: Option<WaveFormat>,
+    // Only filled in for compressed formats that are decoded a block at a time
+    samples_per_block: u16,
+    // From the 'fact' chunk, how many frames there are once the samples are decoded
+    sample_frames: Option<u32>,
+    chunks: Vec<Chunk>,
+    // From any LIST/INFO chunks, keyed by the INFO id eg INAM
+    metadata: BTreeMap<String, String>,
+    // Only in Broadcast WAV files
+    bext: Option<bext::Bext>,
+    // Problems that don't stop the file being decoded
+    warnings: Vec<String>,
+    // Always the WAV defaults unless the samples came from somewhere else
+    signedness: Signedness,
+    endianness: Endianness,
 }
 
 impl std::convert::TryFrom<&mut std::fs::File> for RiffHeader {
     type Error = WavReadError;
     fn try_from(f: &mut std::fs::File) -> Result<Self, Self::Error> {
+        RiffHeader::from_reader(f)
+    }
+}
+
+impl RiffHeader {
+    // TryFrom can't be implemented for every reader because of the blanket impl in core,
+    // so this does the work for anything, files and in memory buffers alike
+    fn from_reader<R: Read>(f: &mut R) -> Result<Self, WavReadError> {
         let mut buf: [u8; 4] = [0; 4];
         f.read_exact(&mut buf)?;
 
-        let header = std::str::from_utf8(&buf)?;
-        if header != "RIFF" {
-            return Err(WavReadError::Header(WavHeaderError::Riff));
+        let container = match &buf {
+            b"RIFF" => Container::Riff,
+            b"RF64" => Container::Rf64,
+            b"BW64" => Container::Bw64,
+            b"RIFX" => Container::Rifx,
+            // Either sort of AIFF, which one comes from the form type
+            b"FORM" => Container::Aiff,
+            b"riff" => Container::Wave64,
+            _ => return Err(WavReadError::from(WavHeaderError::Riff).at(0).found(&buf)),
+        };
+
+        if container == Container::Wave64 {
+            let mut tail: [u8; 12] = [0; 12];
+            f.read_exact(&mut tail)?;
+            if tail != WAVE64_RIFF_GUID_TAIL {
+                return Err(WavReadError::from(WavHeaderError::Riff).at(4).found(&tail));
+            }
+            let file_size = f.read_u64::<LittleEndian>()?;
+
+            let mut wave: [u8; 16] = [0; 16];
+            f.read_exact(&mut wave)?;
+            if wave != WAVE64_WAVE_GUID {
+                return Err(WavReadError::from(WavHeaderError::Wave).at(24).found(&wave));
+            }
+            return Ok(RiffHeader {
+                file_size,
+                container,
+            });
         }
 
-        let mut rc = RiffHeader::default();
+        // For RF64 this is 0xffffffff, and the real size comes from the ds64 chunk
         f.read_exact(&mut buf)?;
-        rc.file_size = u32::from_le_bytes(buf);
+        let file_size = container.endianness().u32_from_bytes(buf).into();
 
         f.read_exact(&mut buf)?;
-        let wave = std::str::from_utf8(&buf)?;
-        if wave != "WAVE" {
-            return Err(WavReadError::Header(WavHeaderError::Wave));
-        }
+        let container = match (container, &buf) {
+            (Container::Aiff, b"AIFC") => Container::Aifc,
+            (container, form_type) if form_type == container.form_type() => container,
+            _ => return Err(WavReadError::from(WavHeaderError::Wave).at(8).found(&buf)),
+        };
 
-        Ok(rc)
+        Ok(RiffHeader {
+            file_size,
+            container,
+        })
     }
 }
 
 impl std::convert::From<RiffHeader> for WaveHeader {
     fn from(riff: RiffHeader) -> Self {
-        let mut rc = WaveHeader::default();
-        rc.riff = riff;
-        rc
+        WaveHeader {
+            endianness: riff.container.endianness(),
+            riff,
+            ..Default::default()
+        }
     }
 }
 
-fn advance_to_data_subchunk(f: &mut std::fs::File) -> Result<(), WavReadError> {
-    let mut buf: [u8; 4] = [0; 4];
-    loop {
-        f.read_exact(&mut buf)?;
-        let subchunk2_name = std::str::from_utf8(&buf)?.to_owned();
-
-        f.read_exact(&mut buf)?;
-        let subchunk2_len = u32::from_le_bytes(buf);
+// Every KSDATAFORMAT_SUBTYPE GUID ends in the same 12 bytes, the first 4 are the old format tag
+const SUBTYPE_GUID_TAIL: [u8; 12] = [
+    0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+fn sub_format_from_guid(guid: &[u8; 16]) -> Result<WaveFormat, WavReadError> {
+    let (tag, tail) = guid.split_at(std::mem::size_of::<u32>());
+    let tag = u32::from_le_bytes(tag.try_into().unwrap());
+    let unrecognised = || WavReadError::from(WavHeaderError::UnrecognisedWaveFormat).found(guid);
+    if tail != SUBTYPE_GUID_TAIL {
+        return Err(unrecognised());
+    }
 
-        if subchunk2_name != "data" {
-            // There is a number of optional
-            f.seek(std::io::SeekFrom::Current(
-                subchunk2_len.try_into().unwrap(),
-            ))?;
-        } else {
-            return Ok(());
-        }
+    match u16::try_from(tag).map(WaveFormat::try_from) {
+        Ok(Ok(WaveFormat::Extensible)) | Ok(Err(_)) | Err(_) => Err(unrecognised()),
+        Ok(format) => format,
     }
 }
 
-impl std::convert::TryFrom<&mut std::fs::File> for WaveHeader {
-    type Error = WavReadError;
-    fn try_from(f: &mut std::fs::File) -> Result<Self, Self::Error> {
-        let riff = RiffHeader::try_from(f.by_ref())?;
-        let mut rc = WaveHeader::from(riff);
-        let mut buf: [u8; 4] = [0; 4];
+const SPEAKER_NAMES: [&str; 18] = [
+    "FL", "FR", "FC", "LFE", "BL", "BR", "FLC", "FRC", "BC", "SL", "SR", "TC", "TFL", "TFC", "TFR",
+    "TBL", "TBC", "TBR",
+];
+
+#[derive(PartialEq, Debug, Clone, Default)]
+struct Chunk {
+    id: [u8; 4],
+    // Where the chunk's id is in the file
+    offset: u64,
+    // Just the data, not including the id and size
+    length: u64,
+    container: Container,
+}
 
-        f.read_exact(&mut buf)?;
-        let fmt = std::str::from_utf8(&buf)?;
-        if fmt != "fmt " {
-            return Err(WavReadError::Header(WavHeaderError::Format));
+impl Chunk {
+    fn from_header(header: &[u8], offset: u64, container: Container) -> Self {
+        if container == Container::Wave64 {
+            let (guid, length) = header.split_at(16);
+            let mut id: [u8; 4] = guid[..4].try_into().unwrap();
+            // The only Wave64 id that isn't the same as its RIFF counterpart
+            if &id == b"list" {
+                id = *b"LIST";
+            }
+            // Wave64 sizes include the GUID and the size itself
+            let length = u64::from_le_bytes(length.try_into().unwrap());
+            return Chunk {
+                id,
+                offset,
+                length: length.saturating_sub(container.chunk_header_len()),
+                container,
+            };
         }
 
-        f.read_exact(&mut buf)?;
-        let fmt_len = u32::from_le_bytes(buf);
-        if fmt_len != 16 {
-            return Err(WavReadError::Header(WavHeaderError::FormatLength));
+        let (id, length) = header.split_at(4);
+        Chunk {
+            id: id.try_into().unwrap(),
+            offset,
+            length: container
+                .endianness()
+                .u32_from_bytes(length.try_into().unwrap())
+                .into(),
+            container,
         }
+    }
 
-        f.read_exact(&mut buf)?;
-        // Fallible conversion from the u16::from_le_bytes page
-        let (af_bytes, num_ch_bytes) = buf.split_at(std::mem::size_of::<u16>());
-        rc.audio_format = WaveFormat::try_from(u16::from_le_bytes(af_bytes.try_into().unwrap()))?;
+    fn data_offset(&self) -> u64 {
+        self.offset
+            .saturating_add(self.container.chunk_header_len())
+    }
 
-        rc.num_channels = u16::from_le_bytes(num_ch_bytes.try_into().unwrap());
+    // RIFF chunks always take up an even number of bytes, so odd ones have a pad byte on the
+    // end. Wave64 pads to a multiple of 8
+    fn padded_length(&self) -> u64 {
+        let alignment = self.container.alignment();
+        self.length.div_ceil(alignment).saturating_mul(alignment)
+    }
 
-        f.read_exact(&mut buf)?;
-        rc.sample_rate = Hertz(u32::from_le_bytes(buf));
+    // 64 bit sizes from Wave64 or ds64 can be big enough to overflow, in which case the chunk
+    // just runs past the end of the file like any other that's too long
+    fn end(&self) -> u64 {
+        self.data_offset().saturating_add(self.padded_length())
+    }
+}
 
-        f.read_exact(&mut buf)?;
-        rc.byte_rate = Hertz(u32::from_le_bytes(buf));
+// Reads chunk headers one after another, keeping track of where each one is. For RF64 the
+// sizes that don't fit in 32 bits are filled in from the ds64 chunk
+struct ChunkWalker {
+    container: Container,
+    offset: u64,
+    // From ds64, the size of 'data' first and then the ones from its table
+    sizes: Vec<([u8; 4], u64)>,
+}
 
-        f.read_exact(&mut buf)?;
-        let (block_bytes, bit_bytes) = buf.split_at(std::mem::size_of::<u16>());
-        rc.block_align = u16::from_le_bytes(block_bytes.try_into().unwrap());
-        rc.bits_per_sample = u16::from_le_bytes(bit_bytes.try_into().unwrap());
+impl ChunkWalker {
+    fn new(container: Container) -> Self {
+        ChunkWalker {
+            container,
+            offset: container.file_header_len(),
+            sizes: Vec::new(),
+        }
+    }
+
+    // Running out of file between chunks is how a walk normally finishes, so that's None
+    fn next_chunk<R: Read>(&mut self, f: &mut R) -> Result<Option<Chunk>, WavReadError> {
+        let mut header = vec![0; self.container.chunk_header_len() as usize];
+        match f.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(WavReadError::from(e).at(self.offset)),
+        }
+
+        let mut chunk = Chunk::from_header(&header, self.offset, self.container);
+        if self.container.has_ds64() && chunk.length == u64::from(u32::MAX) {
+            if let Some((_, size)) = self.sizes.iter().find(|(id, _)| id == &chunk.id) {
+                chunk.length = *size;
+            }
+        }
+        self.offset = chunk.end();
+        Ok(Some(chunk))
+    }
+
+    // Gives back the real RIFF size
+    fn read_ds64<R: Read>(&mut self, f: &mut R, chunk: &Chunk) -> Result<u64, WavReadError> {
+        let contents = read_chunk_contents(f, chunk)?;
+        self.read_ds64_contents(&contents)
+            .map_err(|e| WavReadError::from(e).in_chunk(chunk))
+    }
+
+    fn read_ds64_contents(&mut self, contents: &[u8]) -> std::io::Result<u64> {
+        let mut contents = contents;
+        let riff_size = contents.read_u64::<LittleEndian>()?;
+        self.sizes
+            .push((*b"data", contents.read_u64::<LittleEndian>()?));
+        let _sample_count = contents.read_u64::<LittleEndian>()?;
+        let table_length = contents.read_u32::<LittleEndian>()?;
+        for _ in 0..table_length {
+            let mut id: [u8; 4] = [0; 4];
+            contents.read_exact(&mut id)?;
+            self.sizes.push((id, contents.read_u64::<LittleEndian>()?));
+        }
+        Ok(riff_size)
+    }
+}
+
+// Walks every chunk after the RIFF header and records where it is, so the interesting
+// ones can be found no matter what order they were written in. When recovering, a 'data'
+// chunk with a size that can't be right is taken to run to the end of the file
+fn index_chunks<R: Read + Seek>(
+    f: &mut R,
+    header: &mut WaveHeader,
+    recover_len: Option<u64>,
+) -> Result<(), WavReadError> {
+    let mut walker = ChunkWalker::new(header.riff.container);
+    while let Some(mut chunk) = walker.next_chunk(f)? {
+        if header.riff.container.has_ds64() && &chunk.id == b"ds64" {
+            header.riff.file_size = walker.read_ds64(f, &chunk)?;
+        }
+        if let Some(file_len) = recover_len {
+            let available = file_len.saturating_sub(chunk.data_offset());
+            let is_data = &chunk.id == header.riff.container.data_id();
+            if is_data && (chunk.length == 0 || chunk.length > available) {
+                header.warnings.push(format!(
+                    "The data chunk said it was {} bytes, using the {} up to the end of the file",
+                    chunk.length, available
+                ));
+                chunk.length = available;
+                header.chunks.push(chunk);
+                // A recorder that never finished won't have written anything after the samples
+                return Ok(());
+            }
+        }
+        seek_within(f, chunk.end())?;
+        header.chunks.push(chunk);
+    }
+    Ok(())
+}
+
+// A chunk that's too long can end further on than a file can even seek to. There's nothing
+// after the end of the file anyway, so going there has the same effect
+fn seek_within<R: Seek>(f: &mut R, offset: u64) -> std::io::Result<()> {
+    let file_len = f.seek(std::io::SeekFrom::End(0))?;
+    f.seek(std::io::SeekFrom::Start(offset.min(file_len)))?;
+    Ok(())
+}
+
+// Reads the whole of a chunk's data, and the pad byte after it if there is one
+fn read_chunk_contents<R: Read>(f: &mut R, chunk: &Chunk) -> Result<Vec<u8>, WavReadError> {
+    let mut contents = Vec::new();
+    f.by_ref()
+        .take(chunk.padded_length())
+        .read_to_end(&mut contents)
+        .map_err(|e| WavReadError::from(e).in_chunk(chunk))?;
+    contents.truncate(chunk.length as usize);
+    Ok(contents)
+}
+
+// Each INFO entry is an id, a length, then a nul terminated string padded to an even length
+fn read_info_list(
+    mut list: &[u8],
+    endianness: Endianness,
+    metadata: &mut BTreeMap<String, String>,
+) {
+    while list.len() >= 8 {
+        let (id, rest) = list.split_at(4);
+        let (length, rest) = rest.split_at(4);
+        // Widened so that the pad byte can't overflow a size of 0xffffffff, and clamped to
+        // what's left before going back to an index
+        let length = u64::from(endianness.u32_from_bytes(length.try_into().unwrap()));
+        let available = rest.len() as u64;
+        let value = &rest[..length.min(available) as usize];
+        metadata.insert(
+            String::from_utf8_lossy(id).into_owned(),
+            String::from_utf8_lossy(value)
+                .trim_end_matches('\0')
+                .to_owned(),
+        );
+        list = &rest[(length + length % 2).min(available) as usize..];
+    }
+}
+
+fn info_field_name(id: &str) -> &str {
+    match id {
+        "IART" => "Artist",
+        "ICMT" => "Comment",
+        "ICOP" => "Copyright",
+        "ICRD" => "Creation Date",
+        "IENG" => "Engineer",
+        "IGNR" => "Genre",
+        "IKEY" => "Keywords",
+        "INAM" => "Title",
+        "ISFT" => "Software",
+        "ISRC" => "Source",
+        "ISBJ" => "Subject",
+        "ITCH" => "Technician",
+        _ => "Info",
+    }
+}
+
+impl WaveHeader {
+    // A short label for where the recording came from, made from whichever INFO fields are there
+    fn source_tag(&self) -> Option<String> {
+        let parts: Vec<&str> = ["INAM", "IART", "ICRD"]
+            .iter()
+            .filter_map(|id| self.metadata.get(*id))
+            .map(String::as_str)
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(", "))
+        }
+    }
+
+    // LIST/INFO and bext for WAV files, and the text chunks for AIFF ones
+    fn read_metadata_chunks<R: Read + Seek>(&mut self, f: &mut R) -> Result<(), WavReadError> {
+        let chunks: Vec<Chunk> = self
+            .chunks
+            .iter()
+            .filter(|chunk| {
+                matches!(&chunk.id, b"LIST" | b"bext") || aiff::is_text_chunk(&chunk.id)
+            })
+            .cloned()
+            .collect();
+        for chunk in chunks {
+            f.seek(std::io::SeekFrom::Start(chunk.data_offset()))?;
+            let contents = read_chunk_contents(f, &chunk)?;
+            match &chunk.id {
+                b"LIST" => self.read_list(&contents),
+                b"bext" => self.bext = bext::Bext::from_bytes(&contents),
+                id => self.read_text_chunk(id, &contents),
+            }
+        }
+        Ok(())
+    }
+
+    // Anything too short to hold the count is left alone, as the data still says how much there is
+    fn read_fact(&mut self, contents: &[u8]) {
+        if let Some(count) = contents.get(..4) {
+            let count = self
+                .riff
+                .container
+                .endianness()
+                .u32_from_bytes(count.try_into().unwrap());
+            self.sample_frames = Some(count);
+        }
+    }
+
+    fn read_list(&mut self, contents: &[u8]) {
+        // Other list types such as adtl aren't metadata about the recording
+        if contents.starts_with(b"INFO") {
+            read_info_list(
+                &contents[4..],
+                self.riff.container.endianness(),
+                &mut self.metadata,
+            );
+        }
+    }
+
+    fn find_chunk(&self, id: &[u8; 4]) -> Option<&Chunk> {
+        self.chunks.iter().find(|chunk| &chunk.id == id)
+    }
+
+    fn read_format_chunk<R: Read>(&mut self, f: &mut R, fmt_len: u64) -> Result<(), WavReadError> {
+        if self.riff.container.is_aiff() {
+            return self.read_common_chunk(f, fmt_len);
+        }
+        match self.riff.container.endianness() {
+            Endianness::Little => self.read_format_chunk_as::<LittleEndian, R>(f, fmt_len),
+            Endianness::Big => self.read_format_chunk_as::<BigEndian, R>(f, fmt_len),
+        }
+    }
+
+    fn read_format_chunk_as<B: ByteOrder, R: Read>(
+        &mut self,
+        f: &mut R,
+        fmt_len: u64,
+    ) -> Result<(), WavReadError> {
+        // 16 is the plain PCM layout, anything else has to at least fit the extension size
+        if fmt_len != 16 && fmt_len < 18 {
+            return Err(WavHeaderError::FormatLength.into());
+        }
+
+        let mut tag: [u8; 2] = [0; 2];
+        f.read_exact(&mut tag)?;
+        self.audio_format = WaveFormat::try_from(B::read_u16(&tag)).map_err(|e| e.found(&tag))?;
+        self.num_channels = f.read_u16::<B>()?;
+        self.sample_rate = Hertz(f.read_u32::<B>()?);
+        self.byte_rate = Hertz(f.read_u32::<B>()?);
+        self.block_align = f.read_u16::<B>()?;
+        self.bits_per_sample = f.read_u16::<B>()?;
+
+        let unread = fmt_len - 16;
+        if unread > 0 {
+            let mut extension_bytes: [u8; 2] = [0; 2];
+            f.read_exact(&mut extension_bytes)?;
+            let extension_len = B::read_u16(&extension_bytes);
+            let bad_extension =
+                || WavReadError::from(WavHeaderError::FormatLength).found(&extension_bytes);
+            if u64::from(extension_len) > unread - 2 {
+                return Err(bad_extension());
+            }
+
+            if self.audio_format == WaveFormat::Extensible {
+                if extension_len < 22 {
+                    return Err(bad_extension());
+                }
+                self.valid_bits_per_sample = f.read_u16::<B>()?;
+                self.channel_mask = f.read_u32::<B>()?;
+                let mut guid: [u8; 16] = [0; 16];
+                f.read_exact(&mut guid)?;
+                self.sub_format = Some(sub_format_from_guid(&guid)?);
+            } else if self.audio_format == WaveFormat::ImaAdpcm && extension_len >= 2 {
+                self.samples_per_block = f.read_u16::<B>()?;
+            }
+        } else if self.audio_format == WaveFormat::Extensible {
+            return Err(WavHeaderError::FormatLength.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl std::convert::TryFrom<&mut std::fs::File> for WaveHeader {
+    type Error = WavReadError;
+    fn try_from(f: &mut std::fs::File) -> Result<Self, Self::Error> {
+        WaveHeader::from_reader(f)
+    }
+}
 
-        advance_to_data_subchunk(f)?;
+impl WaveHeader {
+    fn from_reader<R: Read + Seek>(f: &mut R) -> Result<Self, WavReadError> {
+        WaveHeader::read_seekable(f, false)
+    }
+
+    // For files from recorders that crashed or were never closed properly, where the sizes
+    // are 0, 0xffffffff or just wrong. They're worked out from the length of the file instead
+    fn recover<R: Read + Seek>(f: &mut R) -> Result<Self, WavReadError> {
+        WaveHeader::read_seekable(f, true)
+    }
+
+    fn read_seekable<R: Read + Seek>(f: &mut R, lenient: bool) -> Result<Self, WavReadError> {
+        let riff = RiffHeader::from_reader(f)?;
+        let mut rc = WaveHeader::from(riff);
+        let recover_len = if lenient {
+            let start = f.stream_position()?;
+            let file_len = f.seek(std::io::SeekFrom::End(0))?;
+            f.seek(std::io::SeekFrom::Start(start))?;
+            Some(file_len)
+        } else {
+            None
+        };
+        index_chunks(f, &mut rc, recover_len)?;
+        if let Some(file_len) = recover_len {
+            rc.recover_sizes(file_len);
+        }
+
+        let container = rc.riff.container;
+        let fmt = rc
+            .find_chunk(container.format_id())
+            .cloned()
+            .ok_or(WavReadError::from(WavHeaderError::Format))?;
+        f.seek(std::io::SeekFrom::Start(fmt.data_offset()))?;
+        rc.read_format_chunk(f, fmt.length)
+            .map_err(|e| e.in_chunk(&fmt))?;
+        rc.read_metadata_chunks(f)?;
+        if let Some(fact) = rc.find_chunk(b"fact").cloned() {
+            f.seek(std::io::SeekFrom::Start(fact.data_offset()))?;
+            let contents = read_chunk_contents(f, &fact)?;
+            rc.read_fact(&contents);
+        }
+
+        let data = rc
+            .find_chunk(container.data_id())
+            .cloned()
+            .ok_or(WavReadError::from(WavHeaderError::Data))?;
+        let file_len = f.seek(std::io::SeekFrom::End(0))?;
+        f.seek(std::io::SeekFrom::Start(data.data_offset()))?;
+        let skipped = match container.is_aiff() {
+            true => aiff::read_sound_header(f).map_err(|e| e.in_chunk(&data))?,
+            false => 0,
+        };
+        rc.data_offset = data.data_offset() + skipped;
+        let length = data.length.saturating_sub(skipped);
+
+        // Recordings that were cut short often still have the size they were meant to be
+        let available = file_len.saturating_sub(rc.data_offset);
+        rc.data_size = length.min(available);
+        if length > available {
+            rc.warnings.push(format!(
+                "The data chunk should be {} bytes but the file only has {}",
+                length, available
+            ));
+        }
+        f.seek(std::io::SeekFrom::Start(rc.data_offset))?;
         Ok(rc)
     }
+
+    fn recover_sizes(&mut self, file_len: u64) {
+        let container = self.riff.container;
+        if self.find_chunk(container.data_id()).is_none() {
+            // The id can get mangled too, but the samples are still the last thing in the file
+            if let Some(last) = self.chunks.last_mut() {
+                let is_format = &last.id == container.format_id();
+                if !is_format && !matches!(&last.id, b"LIST" | b"ds64") {
+                    self.warnings.push(format!(
+                        "There's no data chunk so the '{}' chunk at byte {} is used instead",
+                        String::from_utf8_lossy(&last.id),
+                        last.offset
+                    ));
+                    last.id = *container.data_id();
+                    last.length = last.length.min(file_len.saturating_sub(last.data_offset()));
+                }
+            }
+        }
+
+        if self.riff.declared_len() != file_len {
+            self.warnings.push(format!(
+                "The RIFF header said the file is {} bytes but it's {}",
+                self.riff.declared_len(),
+                file_len
+            ));
+            self.riff.set_len(file_len);
+        }
+    }
+
+    fn from_raw(raw: &RawFormat, data_size: u64) -> Self {
+        let mut rc = WaveHeader::new(
+            WaveFormat::Pcm,
+            raw.channels,
+            raw.sample_rate,
+            raw.bits_per_sample,
+        );
+        rc.riff.container = Container::Raw;
+        rc.data_size = data_size;
+        rc.signedness = raw.signedness;
+        rc.endianness = raw.endianness;
+        rc
+    }
+
+    // For sources that can't seek, such as stdin. Chunks are handled in the order they arrive,
+    // so 'fmt ' has to come before 'data' and nothing after 'data' is ever looked at
+    fn from_stream<R: Read>(f: &mut R) -> Result<Self, WavReadError> {
+        WaveHeader::read_stream(f, false)
+    }
+
+    // There's no length to check a stream against, so sizes that were never filled in just
+    // mean reading until it ends
+    fn recover_stream<R: Read>(f: &mut R) -> Result<Self, WavReadError> {
+        WaveHeader::read_stream(f, true)
+    }
+
+    fn read_stream<R: Read>(f: &mut R, lenient: bool) -> Result<Self, WavReadError> {
+        let riff = RiffHeader::from_reader(f)?;
+        let mut rc = WaveHeader::from(riff);
+        let container = rc.riff.container;
+        let mut walker = ChunkWalker::new(container);
+        loop {
+            let chunk = match walker.next_chunk(f)? {
+                Some(chunk) => chunk,
+                None => {
+                    let missing = match rc.find_chunk(container.format_id()) {
+                        Some(_) => WavHeaderError::Data,
+                        None => WavHeaderError::Format,
+                    };
+                    return Err(missing.into());
+                }
+            };
+
+            match &chunk.id {
+                b"ds64" if rc.riff.container.has_ds64() => {
+                    rc.riff.file_size = walker.read_ds64(f, &chunk)?;
+                }
+                id if id == container.data_id()
+                    && rc.find_chunk(container.format_id()).is_none() =>
+                {
+                    return Err(WavReadError::from(WavHeaderError::Format).in_chunk(&chunk));
+                }
+                id if id == container.data_id() => {
+                    let skipped = match container.is_aiff() {
+                        true => aiff::read_sound_header(f).map_err(|e| e.in_chunk(&chunk))?,
+                        false => 0,
+                    };
+                    rc.data_offset = chunk.data_offset() + skipped;
+                    rc.data_size = chunk.length.saturating_sub(skipped);
+                    if lenient && (chunk.length == 0 || chunk.length == u64::from(u32::MAX)) {
+                        rc.warnings.push(format!(
+                            "The data chunk said it was {} bytes, reading until the stream ends",
+                            chunk.length
+                        ));
+                        rc.data_size = UNTIL_EOF;
+                    }
+                    rc.chunks.push(chunk);
+                    return Ok(rc);
+                }
+                id if id == container.format_id() => {
+                    let contents = read_chunk_contents(f, &chunk)?;
+                    rc.read_format_chunk(&mut contents.as_slice(), chunk.length)
+                        .map_err(|e| e.in_chunk(&chunk))?;
+                }
+                b"LIST" => {
+                    let contents = read_chunk_contents(f, &chunk)?;
+                    rc.read_list(&contents);
+                }
+                b"fact" => {
+                    let contents = read_chunk_contents(f, &chunk)?;
+                    rc.read_fact(&contents);
+                }
+                b"bext" => {
+                    let contents = read_chunk_contents(f, &chunk)?;
+                    rc.bext = bext::Bext::from_bytes(&contents);
+                }
+                id if aiff::is_text_chunk(id) => {
+                    let contents = read_chunk_contents(f, &chunk)?;
+                    rc.read_text_chunk(id, &contents);
+                }
+                _ => {
+                    std::io::copy(
+                        &mut f.by_ref().take(chunk.padded_length()),
+                        &mut std::io::sink(),
+                    )?;
+                }
+            }
+            rc.chunks.push(chunk);
+        }
+    }
 }
 impl fmt::Display for WaveHeader {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut tbl = Table::new();
         tbl.set_titles(Row::from(vec!["Section", "Data"]));
         tbl.add_row(Row::from(vec![
-            "File Size",
-            &(self.riff.file_size.to_string() + " bytes"),
+            "Container",
+            &self.riff.container.to_string(),
         ]));
+        if !matches!(self.riff.container, Container::Raw | Container::Flac) {
+            tbl.add_row(Row::from(vec![
+                "File Size",
+                &(self.riff.file_size.to_string() + " bytes"),
+            ]));
+        }
         tbl.add_row(Row::from(vec![
             "Audio Format",
             &self.audio_format.to_string(),
@@ -337,59 +1625,320 @@ impl fmt::Display for WaveHeader {
             "Bits per sample",
             &self.bits_per_sample.to_string(),
         ]));
+        let data_size = match self.data_size {
+            UNTIL_EOF => String::from("Until the end of the stream"),
+            size => size.to_string() + " bytes",
+        };
+        tbl.add_row(Row::from(vec!["Data Size", &data_size]));
+        if self.samples_per_block != 0 {
+            tbl.add_row(Row::from(vec![
+                "Samples per block",
+                &self.samples_per_block.to_string(),
+            ]));
+        }
+        if let (WaveFormat::ImaAdpcm, Some(frames)) = (self.sample_format(), self.sample_frames) {
+            tbl.add_row(Row::from(vec!["Sample Frames", &frames.to_string()]));
+        }
+        if let Some(sub_format) = &self.sub_format {
+            tbl.add_row(Row::from(vec!["Sub Format", &sub_format.to_string()]));
+            tbl.add_row(Row::from(vec![
+                "Valid bits per sample",
+                &self.valid_bits_per_sample.to_string(),
+            ]));
+            tbl.add_row(Row::from(vec![
+                "Channel Mask",
+                &format!("{:#010x} {}", self.channel_mask, self.speakers().join(" ")),
+            ]));
+        }
+        for (id, value) in &self.metadata {
+            tbl.add_row(Row::from(vec![
+                &format!("{} ({})", info_field_name(id), id),
+                value,
+            ]));
+        }
+        if let Some(bext) = &self.bext {
+            bext.add_rows(&mut tbl);
+        }
         write!(f, "{}", tbl)
     }
 }
 
-#[derive(Debug)]
-struct WaveFile {
-    header: WaveHeader,
-    ldata: Vec<i16>,
-    rdata: Vec<i16>,
-}
+impl WaveHeader {
+    // The format the samples are actually stored in, which for extensible files is in the GUID
+    fn sample_format(&self) -> &WaveFormat {
+        self.sub_format.as_ref().unwrap_or(&self.audio_format)
+    }
 
-impl Default for WaveFile {
-    fn default() -> Self {
-        WaveFile {
-            header: WaveHeader::default(),
-            ldata: Vec::new(),
-            rdata: Vec::new(),
+    // Speaker positions from the channel mask, in the order the channels are interleaved
+    fn speakers(&self) -> Vec<&'static str> {
+        SPEAKER_NAMES
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| self.channel_mask & (1 << bit) != 0)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    // The number of bytes each sample takes up in a frame. This comes from the block align
+    // rather than the bits per sample, as something like 20 bit audio is padded out to 3 bytes
+    fn sample_width(&self) -> Result<usize, WavReadError> {
+        let block_align = self.block_align;
+        let bad_block_align =
+            || self.format_error(WavHeaderError::BlockAlign, &self.file_bytes(block_align));
+        if self.num_channels == 0 || !block_align.is_multiple_of(self.num_channels) {
+            return Err(bad_block_align());
+        }
+
+        let width = usize::from(block_align / self.num_channels);
+        let needed = usize::from(self.bits_per_sample.div_ceil(8));
+        if width == 0 || width < needed {
+            return Err(bad_block_align());
+        }
+        Ok(width)
+    }
+
+    // A field from 'fmt ' or 'COMM' as the bytes it was read from. The header itself is
+    // always in the container's byte order, even when the samples aren't
+    fn file_bytes(&self, value: u16) -> [u8; 2] {
+        self.riff.container.endianness().u16_to_bytes(value)
+    }
+
+    // Problems with what 'fmt ' said that only show up once the samples are looked at
+    fn format_error(&self, kind: WavHeaderError, found: &[u8]) -> WavReadError {
+        let e = WavReadError::from(kind).found(found);
+        match self.find_chunk(self.riff.container.format_id()) {
+            Some(fmt) => e.in_chunk(fmt),
+            None => e,
+        }
+    }
+
+    // Samples are normalised to -1.0..1.0 so that parse can use the same threshold
+    // no matter what format the file was recorded in
+    fn decode_sample(&self, bytes: &[u8]) -> Result<f32, WavReadError> {
+        match self.endianness {
+            Endianness::Little => self.decode_sample_as::<LittleEndian>(bytes),
+            Endianness::Big => self.decode_sample_as::<BigEndian>(bytes),
+        }
+    }
+
+    fn decode_sample_as<B: ByteOrder>(&self, mut bytes: &[u8]) -> Result<f32, WavReadError> {
+        let width = bytes.len();
+        let unsigned = self.signedness.is_unsigned(width);
+        match (self.sample_format(), width) {
+            // Unsigned samples have silence half way up, 128 for 8 bit ones
+            (WaveFormat::Pcm, 1..=4) => {
+                let full_scale = 1_i64 << (width * 8 - 1);
+                let sample = match unsigned {
+                    true => bytes.read_uint::<B>(width)? as i64 - full_scale,
+                    false => bytes.read_int::<B>(width)?,
+                };
+                Ok(sample as f32 / full_scale as f32)
+            }
+            (WaveFormat::Float, 4) => Ok(bytes.read_f32::<B>()?),
+            (WaveFormat::Float, 8) => Ok(bytes.read_f64::<B>()? as f32),
+            (WaveFormat::Alaw, 1) => Ok(normalise(alaw_to_linear(bytes.read_u8()?))),
+            (WaveFormat::Mulaw, 1) => Ok(normalise(mulaw_to_linear(bytes.read_u8()?))),
+            _ => Err(self.format_error(
+                WavHeaderError::BitsPerSample,
+                &self.file_bytes(self.bits_per_sample),
+            )),
         }
     }
 }
 
+fn normalise(sample: i16) -> f32 {
+    f32::from(sample) / -f32::from(i16::MIN)
+}
+
+// G.711 expansion, as per the ITU reference implementation. Both give a 16 bit linear value
+fn alaw_to_linear(a_val: u8) -> i16 {
+    // Even bits are inverted on the wire
+    let a_val = a_val ^ 0x55;
+    let mantissa = i16::from(a_val & 0x0f) << 4;
+    let segment = (a_val & 0x70) >> 4;
+    let magnitude = match segment {
+        0 => mantissa + 8,
+        1 => mantissa + 0x108,
+        _ => (mantissa + 0x108) << (segment - 1),
+    };
+    if a_val & 0x80 != 0 {
+        magnitude
+    } else {
+        -magnitude
+    }
+}
+
+fn mulaw_to_linear(u_val: u8) -> i16 {
+    // Every bit is inverted on the wire, and the bias of 0x84 is removed after shifting
+    let u_val = !u_val;
+    let magnitude = ((i16::from(u_val & 0x0f) << 3) + 0x84) << ((u_val & 0x70) >> 4);
+    if u_val & 0x80 != 0 {
+        0x84 - magnitude
+    } else {
+        magnitude - 0x84
+    }
+}
+
+// A whole file decoded into memory, for when the samples are wanted rather than the Morse,
+// such as converting them to another format. Decoding streams frames instead
+#[derive(Debug, Default)]
+struct WaveFile {
+    header: WaveHeader,
+    // A list of samples for each channel
+    channels: Vec<Vec<f32>>,
+}
+
 impl std::convert::From<WaveHeader> for WaveFile {
-    fn from(h: WaveHeader) -> Self {
-        let mut rc = WaveFile::default();
-        rc.header = h;
-        rc
+    fn from(header: WaveHeader) -> Self {
+        WaveFile {
+            header,
+            ..Default::default()
+        }
     }
 }
 
 impl std::convert::TryFrom<&mut std::fs::File> for WaveFile {
     type Error = WavReadError;
     fn try_from(f: &mut std::fs::File) -> Result<Self, Self::Error> {
-        let header = WaveHeader::try_from(f.by_ref())?;
+        WaveFile::from_reader(f)
+    }
+}
+
+impl WaveFile {
+    fn from_reader<R: Read + Seek>(f: &mut R) -> Result<Self, WavReadError> {
+        let header = WaveHeader::from_reader(f)?;
         let mut rc = WaveFile::from(header);
-        let mut samples = Vec::new();
-        f.read_to_end(&mut samples)?;
+        rc.read_samples(f)?;
+        Ok(rc)
+    }
 
-        rc.ldata = samples
-            .chunks_exact(2)
-            .step_by(rc.header.num_channels as usize)
-            .map(|mut byte_pair| byte_pair.read_i16::<LittleEndian>().unwrap_or(0))
-            .collect();
+    // Only complete frames are kept, so writing this back out gives a file with sizes that
+    // match what's in it
+    fn recover<R: Read + Seek>(f: &mut R) -> Result<Self, WavReadError> {
+        let header = WaveHeader::recover(f)?;
+        let mut rc = WaveFile::from(header);
+        rc.read_samples(f)?;
+        Ok(rc)
+    }
 
-        if rc.header.num_channels == 2 {
-            rc.rdata = samples
-                .chunks_exact(2)
-                .skip(1)
-                .step_by(2)
-                .map(|mut byte_pair| byte_pair.read_i16::<LittleEndian>().unwrap_or(0))
-                .collect()
+    fn read_samples<R: Read>(&mut self, f: &mut R) -> Result<(), WavReadError> {
+        self.channels = vec![Vec::new(); usize::from(self.header.num_channels)];
+        for frame in Frames::new(&self.header, f)? {
+            for (channel, sample) in self.channels.iter_mut().zip(frame?) {
+                channel.push(sample);
+            }
         }
+        Ok(())
+    }
 
-        Ok(rc)
+    // The same mix the decoder gives each frame as it streams them
+    fn mono(&self) -> Vec<f32> {
+        let frames = self.channels.first().map_or(0, Vec::len);
+        (0..frames)
+            .map(|i| {
+                let frame: Vec<f32> = self.channels.iter().map(|c| c[i]).collect();
+                ChannelSelection::Mix.pick(&frame)
+            })
+            .collect()
+    }
+}
+
+// Decodes the data chunk a frame at a time through a buffer, giving a sample for each channel.
+// Reading stops at the end of the data so any chunks after it aren't taken for samples
+struct Frames<'a, R: Read> {
+    header: &'a WaveHeader,
+    reader: std::io::BufReader<std::io::Take<R>>,
+    width: usize,
+    frame: Vec<u8>,
+    // Where the next frame starts in the file
+    offset: u64,
+    // For formats that are stored a block of frames at a time, how many frames are in each
+    // block and what's left of the one being read
+    block_frames: Option<usize>,
+    decoded: std::collections::VecDeque<Vec<f32>>,
+    // How many frames the 'fact' chunk says are left, as the last block can be padded out
+    remaining: Option<u64>,
+}
+
+impl<'a, R: Read> Frames<'a, R> {
+    fn new(header: &'a WaveHeader, reader: R) -> Result<Self, WavReadError> {
+        let (width, block_frames, remaining) = match header.sample_format() {
+            WaveFormat::ImaAdpcm => (
+                0,
+                Some(header.adpcm_block_frames()?),
+                header.sample_frames.map(u64::from),
+            ),
+            _ => (header.sample_width()?, None, None),
+        };
+        Ok(Frames {
+            header,
+            reader: std::io::BufReader::new(reader.take(header.data_size)),
+            width,
+            frame: vec![0; usize::from(header.block_align)],
+            offset: header.data_offset,
+            block_frames,
+            decoded: std::collections::VecDeque::new(),
+            remaining,
+        })
+    }
+
+    fn next_from_block(&mut self, block_frames: usize) -> Option<Result<Vec<f32>, WavReadError>> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        if self.decoded.is_empty() {
+            // Unlike frames, whatever there is of the last block can still be decoded
+            let mut block = Vec::with_capacity(self.frame.len());
+            if let Err(e) = self
+                .reader
+                .by_ref()
+                .take(self.frame.len() as u64)
+                .read_to_end(&mut block)
+            {
+                return Some(Err(WavReadError::from(e).at(self.offset)));
+            }
+            let channels = usize::from(self.header.num_channels);
+            self.decoded = adpcm::decode_block(&block, channels)
+                .into_iter()
+                .take(block_frames)
+                .collect();
+            self.offset += block.len() as u64;
+        }
+
+        let frame = self.decoded.pop_front()?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+        Some(Ok(frame))
+    }
+
+    // How much of the data the source ran out before giving
+    fn missing(&self) -> u64 {
+        self.reader.get_ref().limit()
+    }
+}
+
+impl<R: Read> Iterator for Frames<'_, R> {
+    type Item = Result<Vec<f32>, WavReadError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(block_frames) = self.block_frames {
+            return self.next_from_block(block_frames);
+        }
+        if let Err(e) = self.reader.read_exact(&mut self.frame) {
+            // A partial frame at the end can't be decoded so it's the same as finishing cleanly
+            if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                return None;
+            }
+            return Some(Err(WavReadError::from(e).at(self.offset)));
+        }
+
+        let frame = self
+            .frame
+            .chunks_exact(self.width)
+            .map(|sample| self.header.decode_sample(sample))
+            .collect();
+        self.offset += self.frame.len() as u64;
+        Some(frame)
     }
 }
 
@@ -399,27 +1948,91 @@ impl fmt::Display for WaveFile {
     }
 }
 
+// How long the parts of the Morse last, measured in Audacity on an 8kHz recording sent with
+// a 60ms dot. The limits sit between what's expected of each so that a fist that's a bit off
+// still decodes: a dot is 1 unit, a dash and the gap between letters 3, and between words 7
+const ZERO_CROSSING_MS: f64 = 0.375;
+const SHORT_MS: f64 = 50.0;
+const LONG_MS: f64 = 150.0;
+const SYMBOL_GAP_MS: f64 = 125.0;
+const LETTER_GAP_MS: f64 = 250.0;
+
+// The durations above as sample counts at the rate of the file being decoded
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Timing {
+    // Shorter gaps are just the sine wave passing through 0 in the middle of a symbol
+    zero_crossing: u64,
+    short: u64,
+    long: u64,
+    // Longer gaps end the letter, and longer than that the word
+    symbol_gap: u64,
+    letter_gap: u64,
+}
+
+impl Timing {
+    fn from_rate(sample_rate: u32) -> Self {
+        let samples = |ms: f64| (ms * f64::from(sample_rate) / 1000.0).round() as u64;
+        Timing {
+            zero_crossing: samples(ZERO_CROSSING_MS),
+            short: samples(SHORT_MS),
+            long: samples(LONG_MS),
+            symbol_gap: samples(SYMBOL_GAP_MS),
+            letter_gap: samples(LETTER_GAP_MS),
+        }
+    }
+
+    // Anything shorter than a short is taken to be noise
+    fn symbol(&self, sample_count: u64) -> Result<Symbol, SymbolError> {
+        if sample_count > self.long {
+            return Ok(Symbol::Long);
+        }
+        if sample_count > self.short {
+            return Ok(Symbol::Short);
+        }
+        Err(SymbolError)
+    }
+}
+
 #[derive(Debug)]
 struct ParserStatus {
+    timing: Timing,
     over_threshold: u64,
     under_threshold: u64,
 }
 
-impl Default for ParserStatus {
-    fn default() -> ParserStatus {
+// Anything that parse can compare against a threshold
+trait Sample: Copy + PartialOrd {
+    fn magnitude(self) -> Self;
+}
+
+impl Sample for i16 {
+    fn magnitude(self) -> Self {
+        // -32768 has no positive counterpart, but it's definitely loud
+        self.checked_abs().unwrap_or(i16::MAX)
+    }
+}
+
+impl Sample for f32 {
+    fn magnitude(self) -> Self {
+        self.abs()
+    }
+}
+
+impl ParserStatus {
+    fn new(timing: Timing) -> Self {
         ParserStatus {
+            timing,
             over_threshold: 0,
             under_threshold: 0,
         }
     }
-}
 
-impl ParserStatus {
     // End of a long/short
     fn is_end_of_symbol(&self) -> bool {
         // Small gap immediately after some stuff over the threshold
-        // This was measured in audacity
-        self.under_threshold > 3 && self.under_threshold <= 1000 && self.over_threshold != 0
+        self.under_threshold > self.timing.zero_crossing
+            && self.under_threshold <= self.timing.symbol_gap
+            && self.over_threshold != 0
     }
 
     fn over(&mut self) {
@@ -441,21 +2054,60 @@ impl ParserStatus {
 
     fn is_end_of_letter(&self) -> bool {
         // Big gap at the end of the
-        self.under_threshold > 1000 && self.under_threshold <= 2000 && self.over_threshold == 0
+        self.under_threshold > self.timing.symbol_gap
+            && self.under_threshold <= self.timing.letter_gap
+            && self.over_threshold == 0
     }
 
     fn is_space(&self) -> bool {
         // Bigger gap
-        self.under_threshold > 2000 && self.over_threshold == 0
+        self.under_threshold > self.timing.letter_gap && self.over_threshold == 0
     }
 }
 
-fn parse(samples: &[i16], threshold: i16) -> String {
-    let mut status = ParserStatus::default();
+// A decoded letter, or the space between words, and the sample it started at
+#[derive(Debug, PartialEq)]
+struct Letter {
+    sample: u64,
+    text: &'static str,
+}
+
+fn text_of(letters: &[Letter]) -> String {
+    let rc: String = letters.iter().map(|letter| letter.text).collect();
+    rc.trim_end().to_owned()
+}
+
+// Each word and the frame it starts at, which is where the labels go
+fn word_labels(letters: &[Letter]) -> Vec<(u64, String)> {
+    let mut labels: Vec<(u64, String)> = Vec::new();
+    let mut in_word = false;
+    for letter in letters {
+        if letter.text == " " {
+            in_word = false;
+        } else if in_word {
+            if let Some((_, word)) = labels.last_mut() {
+                word.push_str(letter.text);
+            }
+        } else {
+            labels.push((letter.sample, letter.text.to_owned()));
+            in_word = true;
+        }
+    }
+    labels
+}
+
+fn parse_letters<T, I>(samples: I, threshold: T, timing: Timing) -> Vec<Letter>
+where
+    T: Sample,
+    I: IntoIterator,
+    I::Item: Borrow<T>,
+{
+    let mut status = ParserStatus::new(timing);
     let mut symbols: Vec<Symbol> = Vec::new();
-    let mut rc = String::new();
-    for sample in samples {
-        let abs = sample.checked_abs().unwrap_or(threshold + 1);
+    let mut letter_start = 0;
+    let mut rc: Vec<Letter> = Vec::new();
+    for (i, sample) in (0_u64..).zip(samples) {
+        let abs = sample.borrow().magnitude();
 
         if abs > threshold {
             status.over();
@@ -467,17 +2119,24 @@ fn parse(samples: &[i16], threshold: i16) -> String {
             // If the symbol doesn't successfully parse then it's less than a
             // unit (short). In which case assume it's an erroneous blip in the sinwave
             // so go to pretend it never happened by just going to the next sample
-            let symbol = Symbol::try_from(status.over_threshold);
+            let symbol = timing.symbol(status.over_threshold);
+            let symbol_start = i + 1 - status.under_threshold - status.over_threshold;
 
             status.reset_over();
             if symbol.is_err() {
                 continue;
             }
 
+            if symbols.is_empty() {
+                letter_start = symbol_start;
+            }
             symbols.push(symbol.unwrap());
         } else if status.is_end_of_letter() && !symbols.is_empty() {
             // If it's the end of the word I can try to decode the symbols gathered
-            rc += decode(&symbols);
+            rc.push(Letter {
+                sample: letter_start,
+                text: decode(&symbols),
+            });
             symbols.clear();
         } else if status.is_space() {
             // If it's a really long gap I can add a space to the output string
@@ -486,8 +2145,11 @@ fn parse(samples: &[i16], threshold: i16) -> String {
 
             // If there is loads of gap it might be that the operator is having a break etc. In which case I don't
             // want loads of spaces in the string, so only add spaces if there isn't already
-            if !rc.ends_with(' ') {
-                rc += " ";
+            if rc.last().is_none_or(|letter| letter.text != " ") {
+                rc.push(Letter {
+                    sample: i + 1 - status.under_threshold,
+                    text: " ",
+                });
             }
             status.reset_under();
         }
@@ -497,10 +2159,13 @@ fn parse(samples: &[i16], threshold: i16) -> String {
         // probably the end of a word since it's the end of the file so
         // try to decode it. No guarantee that the final symbol has ended with a
         // long enough gap to trigger an end of symbol decode
-        rc += decode(&symbols);
+        rc.push(Letter {
+            sample: letter_start,
+            text: decode(&symbols),
+        });
         symbols.clear();
     }
-    rc.trim_end().to_owned()
+    rc
 }
 
 fn decode(morse: &[Symbol]) -> &'static str {
@@ -719,49 +2384,185 @@ impl fmt::Display for SymbolError {
     }
 }
 
-impl std::convert::TryFrom<u64> for Symbol {
-    type Error = SymbolError;
-    fn try_from(sample_count: u64) -> Result<Self, Self::Error> {
-        // A dot is one unit
-        // A dash is three units
-        if sample_count > 1200 {
-            return Ok(Symbol::Long);
-        }
-        if sample_count > 400 {
-            return Ok(Symbol::Short);
-        }
-        Err(SymbolError)
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // The text on its own, without the times the letters were sent at
+    fn decode_samples<R: Read>(
+        header: &WaveHeader,
+        f: &mut R,
+        channel: &ChannelSelection,
+    ) -> Result<String, WavReadError> {
+        decode_letters(header, f, channel).map(|letters| text_of(&letters))
+    }
+
+    // The whole text at once, for giving samples straight to the parser
+    fn parse<T, I>(samples: I, threshold: T, timing: Timing) -> String
+    where
+        T: Sample,
+        I: IntoIterator,
+        I::Item: Borrow<T>,
+    {
+        text_of(&parse_letters(samples, threshold, timing))
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
     #[test]
     fn parse_args_test_not_enough() {
-        let args = vec!["Progname".to_string()];
-        let cfg = Config::try_from(args.as_slice());
+        let argv = args(&["Progname"]);
+        let cfg = Config::try_from(argv.as_slice());
 
         assert_eq!(cfg.err().unwrap(), ConfigParseError::NotEnoughArgs);
     }
 
     #[test]
     fn parse_args_test_just_right() {
-        let args = vec!["Progname".to_string(), "arg".to_string()];
-        let cfg = Config::try_from(args.as_slice()).unwrap();
-        assert_eq!(&args[1], cfg.fname);
-        assert_eq!(args[1], *cfg.fname);
+        let argv = args(&["Progname", "arg"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(&argv[1], cfg.fname);
+        assert_eq!(argv[1], *cfg.fname);
+    }
+
+    #[test]
+    fn parse_args_test_channel() {
+        let argv = args(&["Progname", "--channel", "2", "arg"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(cfg.channel, ChannelSelection::Single(2));
+        assert_eq!(cfg.fname, "arg");
+
+        let argv = args(&["Progname", "arg", "--mono"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(cfg.channel, ChannelSelection::Mix);
+    }
+
+    #[test]
+    fn parse_args_test_lint() {
+        let argv = args(&["Progname", "lint", "--json", "arg"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(cfg.command, Command::Lint);
+        assert_eq!(cfg.output, OutputFormat::Json);
+        assert_eq!(cfg.fname, "arg");
+
+        // Only a subcommand when it comes first
+        let argv = args(&["Progname", "--mono", "lint"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(cfg.command, Command::Decode);
+        assert_eq!(cfg.fname, "lint");
+
+        let argv = args(&["Progname", "chunks", "arg"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(cfg.command, Command::Chunks);
+        assert_eq!(cfg.fname, "arg");
+    }
+
+    #[test]
+    fn parse_args_test_repair() {
+        let argv = args(&["Progname", "--lenient", "arg"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert!(cfg.lenient);
+        assert!(!cfg.timestamps);
+        assert_eq!(cfg.repair, None);
+
+        let argv = args(&["Progname", "--timestamps", "arg"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert!(cfg.timestamps);
+        assert_eq!(cfg.fname, "arg");
+
+        let argv = args(&["Progname", "arg", "--labels", "labelled.wav"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(cfg.labels.unwrap(), "labelled.wav");
+
+        let argv = args(&[
+            "Progname", "--raw", "--rate", "8000", "--labels", "out", "arg",
+        ]);
+        let cfg = Config::try_from(argv.as_slice());
+        assert_eq!(cfg.err().unwrap(), ConfigParseError::LabelsNeedWav);
+
+        let argv = args(&["Progname", "arg", "--repair", "fixed.wav"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(cfg.repair.unwrap(), "fixed.wav");
+        assert_eq!(cfg.fname, "arg");
+
+        let argv = args(&["Progname", "arg", "--repair"]);
+        let cfg = Config::try_from(argv.as_slice());
+        assert_eq!(cfg.err().unwrap(), ConfigParseError::NotEnoughArgs);
+
+        let argv = args(&["Progname", "--convert", "plain.wav", "--float", "arg"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(cfg.convert.unwrap(), "plain.wav");
+        assert!(cfg.float);
+        assert_eq!(cfg.fname, "arg");
+
+        let argv = args(&["Progname", "--repair", "a.wav", "--convert", "b.wav", "arg"]);
+        let cfg = Config::try_from(argv.as_slice());
+        assert_eq!(cfg.err().unwrap(), ConfigParseError::RepairOrConvert);
+    }
+
+    #[test]
+    fn parse_args_test_raw() {
+        let argv = args(&[
+            "Progname",
+            "--raw",
+            "--rate",
+            "48000",
+            "--channels",
+            "2",
+            "--bits",
+            "24",
+            "--unsigned",
+            "--big-endian",
+            "-",
+        ]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(
+            cfg.raw,
+            Some(RawFormat {
+                sample_rate: 48000,
+                channels: 2,
+                bits_per_sample: 24,
+                signedness: Signedness::Unsigned,
+                endianness: Endianness::Big,
+            })
+        );
+
+        let argv = args(&["Progname", "--raw", "arg"]);
+        let cfg = Config::try_from(argv.as_slice()).unwrap();
+        assert_eq!(cfg.raw, Some(RawFormat::default()));
+
+        for bad in &[
+            ["--bits", "12", "--channels", "1"],
+            ["--rate", "0", "--channels", "1"],
+            ["--channels", "x", "--bits", "8"],
+            // The block align doesn't fit in a u16
+            ["--channels", "40000", "--bits", "32"],
+            // Nor the byte rate in a u32
+            ["--rate", "4000000000", "--bits", "32"],
+        ] {
+            let argv = args(&["Progname", "--raw", bad[0], bad[1], bad[2], bad[3], "arg"]);
+            let cfg = Config::try_from(argv.as_slice());
+            assert_eq!(cfg.err().unwrap(), ConfigParseError::InvalidRawFormat);
+        }
+    }
+
+    #[test]
+    fn parse_args_test_bad_channel() {
+        let argv = args(&["Progname", "--channel", "left", "arg"]);
+        let cfg = Config::try_from(argv.as_slice());
+        assert_eq!(cfg.err().unwrap(), ConfigParseError::InvalidChannel);
     }
 
     #[test]
     fn parse_args_test_too_many() {
-        let args = vec!["one".to_string(), "arg".to_string(), "extra".to_string()];
-        let cfg = Config::try_from(args.as_slice());
+        let argv = args(&["one", "arg", "extra"]);
+        let cfg = Config::try_from(argv.as_slice());
         assert_eq!(cfg.err().unwrap(), ConfigParseError::TooManyArgs);
     }
 
-    fn get_full_path_of_test_resource(rname: &str) -> std::path::PathBuf {
+    pub(super) fn get_full_path_of_test_resource(rname: &str) -> std::path::PathBuf {
         let s = std::env::var("CARGO_MANIFEST_DIR")
             .unwrap_or_else(|_| String::from(std::env::current_dir().unwrap().to_str().unwrap()))
             + "/"
@@ -775,65 +2576,89 @@ mod tests {
         WaveHeader::try_from(&mut f)
     }
 
+    fn header_error<T: fmt::Debug>(result: Result<T, WavReadError>) -> WavHeaderError {
+        match result {
+            Err(WavReadError::Header(kind, _)) => kind,
+            other => panic!("Expected a header error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn read_wave_file_test_file_not_found() {
         let metadata = get_metadata("doesn't exist");
-        assert_eq!(
-            metadata.err().unwrap(),
-            WavReadError::FileIO(std::io::ErrorKind::NotFound)
-        );
+        match metadata.err().unwrap() {
+            WavReadError::FileIO(e, _) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+            other => panic!("Expected an IO error, got {:?}", other),
+        }
     }
     #[test]
     fn read_wave_file_test_file_no_riff() {
         let metadata = get_metadata("test_input_no_riff.wav");
-        assert_eq!(
-            metadata.err().unwrap(),
-            WavReadError::Header(WavHeaderError::Riff)
-        );
+        assert_eq!(header_error(metadata), WavHeaderError::Riff);
     }
 
     #[test]
     fn read_wave_file_test_file_no_wave() {
         let metadata = get_metadata("test_input_no_wave.wav");
-        assert_eq!(
-            metadata.err().unwrap(),
-            WavReadError::Header(WavHeaderError::Wave)
-        );
+        assert_eq!(header_error(metadata), WavHeaderError::Wave);
     }
 
     #[test]
     fn read_wave_file_test_file_no_fmt() {
         let metadata = get_metadata("test_input_no_fmt.wav");
-        assert_eq!(
-            metadata.err().unwrap(),
-            WavReadError::Header(WavHeaderError::Format)
-        );
+        assert_eq!(header_error(metadata), WavHeaderError::Format);
     }
 
     #[test]
     fn read_wave_file_test_file_no_fmt_len() {
         let metadata = get_metadata("test_input_no_fmt_len.wav");
-        assert_eq!(
-            metadata.err().unwrap(),
-            WavReadError::Header(WavHeaderError::FormatLength)
-        );
+        assert_eq!(header_error(metadata), WavHeaderError::FormatLength);
     }
 
     #[test]
     fn read_wave_file_test_file_wrong_fmt() {
         let metadata = get_metadata("test_input_wrong_fmt.wav");
         assert_eq!(
-            metadata.err().unwrap(),
-            WavReadError::Header(WavHeaderError::UnrecognisedWaveFormat)
+            header_error(metadata),
+            WavHeaderError::UnrecognisedWaveFormat
         );
     }
 
     #[test]
     fn read_wave_file_test_file_no_data() {
         let metadata = get_metadata("test_input_no_data.wav");
+        assert_eq!(header_error(metadata), WavHeaderError::Data);
+    }
+
+    #[test]
+    fn header_error_context() {
+        let metadata = get_metadata("test_input_no_riff.wav");
+        let e = metadata.err().unwrap();
+        assert_eq!(
+            e.to_string(),
+            "Error reading from WAV file: 'RIFF' wasn't at the start of the file (at byte 0, \
+             found 52 48 46 46 \"RHFF\")"
+        );
+
+        let e = get_metadata("test_input_wrong_fmt.wav").err().unwrap();
+        match &e {
+            WavReadError::Header(WavHeaderError::UnrecognisedWaveFormat, context) => {
+                assert_eq!(context.chunk, Some(*b"fmt "));
+                assert_eq!(context.offset, Some(12));
+                assert_eq!(context.found, [2, 0]);
+            }
+            other => panic!("Expected the format to be rejected, got {:?}", other),
+        }
+        assert!(e.source().is_none());
+    }
+
+    #[test]
+    fn io_error_is_the_source() {
+        let e = get_metadata("doesn't exist").err().unwrap();
+        let source = e.source().unwrap();
         assert_eq!(
-            metadata.err().unwrap(),
-            WavReadError::FileIO(std::io::ErrorKind::UnexpectedEof)
+            source.downcast_ref::<std::io::Error>().unwrap().kind(),
+            std::io::ErrorKind::NotFound
         );
     }
 
@@ -848,10 +2673,248 @@ mod tests {
         expected.byte_rate = Hertz(16000);
         expected.block_align = 2;
         expected.bits_per_sample = 16;
+        expected.data_size = 636_928;
+        expected.data_offset = 78;
+        expected.chunks = vec![
+            Chunk {
+                id: *b"fmt ",
+                offset: 12,
+                length: 16,
+                ..Default::default()
+            },
+            Chunk {
+                id: *b"LIST",
+                offset: 36,
+                length: 26,
+                ..Default::default()
+            },
+            Chunk {
+                id: *b"data",
+                offset: 70,
+                length: 636_928,
+                ..Default::default()
+            },
+        ];
+        expected
+            .metadata
+            .insert(String::from("ISFT"), String::from("Lavf58.29.100"));
 
         assert_eq!(metadata.unwrap(), expected);
     }
 
+    #[test]
+    fn read_wave_file_test_info_metadata() {
+        let metadata = get_metadata("test_input_info.wav").unwrap();
+        assert_eq!(metadata.metadata["INAM"], "GB3RW beacon");
+        assert_eq!(metadata.metadata["IART"], "M0ABC");
+        assert_eq!(metadata.metadata["ICRD"], "2020-05-01");
+        assert_eq!(metadata.metadata["ICMT"], "Odd length");
+        assert_eq!(
+            metadata.source_tag().unwrap(),
+            "GB3RW beacon, M0ABC, 2020-05-01"
+        );
+    }
+
+    #[test]
+    fn info_entry_longer_than_list() {
+        let mut list = b"INAMxxxxbeacon\0".to_vec();
+        list[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut metadata = BTreeMap::new();
+        read_info_list(&list, Endianness::Little, &mut metadata);
+        assert_eq!(metadata["INAM"], "beacon");
+        assert_eq!(metadata.len(), 1);
+    }
+
+    #[test]
+    fn read_wave_file_stops_at_data_end() {
+        let file = get_full_path_of_test_resource("test_input_trailing_chunk.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.data_size, 4);
+        assert!(wave.header.warnings.is_empty());
+        assert_eq!(wave.channels[0], [0.0, 0.5]);
+    }
+
+    #[test]
+    fn read_wave_file_truncated_data() {
+        let file = get_full_path_of_test_resource("test_input_truncated.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.data_size, 4);
+        assert_eq!(wave.header.warnings.len(), 1);
+        assert_eq!(wave.channels[0], [0.0, 0.5]);
+    }
+
+    #[test]
+    fn read_stream_truncated_data() {
+        let bytes =
+            std::fs::read(get_full_path_of_test_resource("test_input_truncated.wav")).unwrap();
+        let mut stream = bytes.as_slice();
+        let header = WaveHeader::from_stream(&mut stream).unwrap();
+        assert_eq!(header.data_size, 100);
+        let mut frames = Frames::new(&header, stream).unwrap();
+        assert_eq!(frames.by_ref().count(), 2);
+        assert_eq!(frames.missing(), 96);
+    }
+
+    #[test]
+    fn recover_unfinished_file() {
+        let file = get_full_path_of_test_resource("test_input_unfinished.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.data_size, 0);
+        assert_eq!(wave.channels[0], []);
+
+        file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        let wave = WaveFile::recover(&mut file).unwrap();
+        assert_eq!(wave.header.data_size, 7);
+        assert_eq!(wave.header.riff.file_size, 43);
+        assert_eq!(wave.header.warnings.len(), 2);
+        // The odd byte on the end isn't a whole frame
+        assert_eq!(wave.channels[0], [0.0, 0.5, -0.5]);
+
+        let mut repaired = Vec::new();
+        file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        let header = WaveHeader::recover(&mut file).unwrap();
+        header.write_repaired(&mut file, &mut repaired).unwrap();
+        let repaired = WaveFile::from_reader(&mut std::io::Cursor::new(repaired)).unwrap();
+        assert_eq!(repaired.header.data_size, 6);
+        assert_eq!(repaired.header.warnings, Vec::<String>::new());
+        assert_eq!(repaired.channels, wave.channels);
+    }
+
+    #[test]
+    fn recover_unfinished_stream() {
+        let bytes =
+            std::fs::read(get_full_path_of_test_resource("test_input_unfinished.wav")).unwrap();
+        let mut stream = bytes.as_slice();
+        let header = WaveHeader::recover_stream(&mut stream).unwrap();
+        assert_eq!(header.data_size, UNTIL_EOF);
+        let samples: Vec<f32> = Frames::new(&header, stream)
+            .unwrap()
+            .map(|frame| frame.unwrap()[0])
+            .collect();
+        assert_eq!(samples, [0.0, 0.5, -0.5]);
+    }
+
+    #[test]
+    fn recover_mangled_data_id() {
+        let file = get_full_path_of_test_resource("test_input_no_data.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let header = WaveHeader::recover(&mut file).unwrap();
+        assert_eq!(header.data_size, 636_928);
+        assert_eq!(header.warnings.len(), 1);
+        let text = decode_samples(&header, &mut file, &ChannelSelection::default()).unwrap();
+        assert!(text.trim_start().starts_with("hello!"));
+    }
+
+    #[test]
+    fn read_rifx_file() {
+        let file = get_full_path_of_test_resource("test_input_rifx.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.riff.container, Container::Rifx);
+        assert_eq!(wave.header.riff.file_size, 68);
+        assert_eq!(wave.header.sample_rate, Hertz(8000));
+        assert_eq!(wave.header.block_align, 2);
+        assert_eq!(wave.header.metadata["INAM"], "rifx");
+        assert_eq!(wave.channels[0], [0.0, 0.5, -0.5]);
+
+        let bytes = std::fs::read(get_full_path_of_test_resource("test_input_rifx.wav")).unwrap();
+        let mut stream = bytes.as_slice();
+        let header = WaveHeader::from_stream(&mut stream).unwrap();
+        assert_eq!(header, wave.header);
+    }
+
+    #[test]
+    fn rifx_errors_show_bytes_as_found() {
+        let bytes = std::fs::read(get_full_path_of_test_resource("test_input_rifx.wav")).unwrap();
+        let found = |bytes: &[u8]| {
+            let mut f = std::io::Cursor::new(bytes);
+            let e = match WaveHeader::from_reader(&mut f) {
+                Ok(header) => Frames::new(&header, &mut f).err().unwrap(),
+                Err(e) => e,
+            };
+            match e {
+                WavReadError::Header(_, context) => context.found,
+                other => panic!("Expected a header error, got {:?}", other),
+            }
+        };
+
+        let mut wrong_format = bytes.clone();
+        wrong_format[20..22].copy_from_slice(&[0x00, 0x02]);
+        assert_eq!(found(&wrong_format), [0x00, 0x02]);
+
+        let mut wrong_block_align = bytes;
+        wrong_block_align[32..34].copy_from_slice(&[0x00, 0x01]);
+        assert_eq!(found(&wrong_block_align), [0x00, 0x01]);
+    }
+
+    #[test]
+    fn read_rf64_file() {
+        let file = get_full_path_of_test_resource("test_input_rf64.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.riff.container, Container::Rf64);
+        assert_eq!(wave.header.riff.file_size, 98);
+        // Both of these have 0xffffffff as their size, one is from the table in ds64
+        assert_eq!(wave.header.find_chunk(b"JUNK").unwrap().length, 2);
+        assert_eq!(wave.header.find_chunk(b"data").unwrap().length, 4);
+        assert_eq!(wave.channels[0], [0.0, 0.5]);
+    }
+
+    #[test]
+    fn read_wave64_file() {
+        let bytes = std::fs::read(get_full_path_of_test_resource("test_input.w64")).unwrap();
+        let wave = WaveFile::from_reader(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(wave.header.riff.container, Container::Wave64);
+        assert_eq!(wave.header.riff.file_size, bytes.len() as u64);
+        assert_eq!(wave.header.sample_rate, Hertz(8000));
+        assert_eq!(wave.header.metadata["INAM"], "w64");
+        assert_eq!(wave.header.find_chunk(b"data").unwrap().length, 8);
+        assert_eq!(wave.channels[0], [0.0, 0.5, -0.5, -1.0]);
+
+        let header = WaveHeader::from_stream(&mut bytes.as_slice()).unwrap();
+        assert_eq!(header.sample_rate, Hertz(8000));
+    }
+
+    #[test]
+    fn wave64_sizes_that_overflow() {
+        let bytes = std::fs::read(get_full_path_of_test_resource("test_input.w64")).unwrap();
+        // The 'list' chunk at 80, then 'data' at 120
+        for (offset, data_found) in &[(80, false), (120, true)] {
+            let mut bytes = bytes.clone();
+            bytes[offset + 16..offset + 24].copy_from_slice(&u64::MAX.to_le_bytes());
+            let mut f = std::io::Cursor::new(&bytes);
+            // Runs past the end, so nothing is found after it
+            let header = WaveHeader::from_reader(&mut f);
+            assert_eq!(header.is_ok(), *data_found);
+            if let Ok(header) = header {
+                assert_eq!(header.data_size, 8);
+            }
+            assert!(lint::lint(&mut f).unwrap().errors() > 0);
+            assert!(tree::read_tree(&mut f).is_ok());
+        }
+
+        let mut bytes = bytes;
+        bytes[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        let header = WaveHeader::recover(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(header.riff.declared_len(), bytes.len() as u64);
+    }
+
+    #[test]
+    fn read_wave_file_test_chunks_in_any_order() {
+        let file = get_full_path_of_test_resource("test_input_chunk_order.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        let ids: Vec<&[u8; 4]> = wave.header.chunks.iter().map(|c| &c.id).collect();
+        assert_eq!(ids, [b"JUNK", b"LIST", b"fmt ", b"data"]);
+        // JUNK is 3 bytes long so is followed by a pad byte
+        assert_eq!(wave.header.chunks[1].offset, 12 + 8 + 4);
+        assert_eq!(wave.header.sample_rate, Hertz(8000));
+        assert_eq!(wave.channels[0], [0.0, 0.5]);
+    }
+
     #[test]
     fn read_data_from_wave_file() {
         let file = get_full_path_of_test_resource("test_input_short.wav");
@@ -859,7 +2922,420 @@ mod tests {
         let wave = WaveFile::try_from(&mut file);
         assert!(wave.is_ok());
         let wave = wave.unwrap();
-        assert_eq!(wave.ldata, [0, 1, 2, 3, 4, 5]);
+        let expected: Vec<f32> = [0i16, 1, 2, 3, 4, 5]
+            .iter()
+            .map(|s| f32::from(*s) / 32768.0)
+            .collect();
+        assert_eq!(wave.channels[0], expected);
+    }
+
+    #[test]
+    fn read_8_bit_data_from_wave_file() {
+        let file = get_full_path_of_test_resource("test_input_8bit.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.bits_per_sample, 8);
+        assert_eq!(wave.channels[0], [0.0, 0.5, -1.0]);
+        assert_eq!(wave.channels[1], [-0.5, 127.0 / 128.0, 0.0]);
+    }
+
+    #[test]
+    fn read_24_bit_data_from_wave_file() {
+        let file = get_full_path_of_test_resource("test_input_24bit.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.bits_per_sample, 24);
+        assert_eq!(wave.channels[0], [0.0, 0.5, -1.0, -0.25]);
+    }
+
+    #[test]
+    fn read_32_bit_data_from_wave_file() {
+        let file = get_full_path_of_test_resource("test_input_32bit.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.bits_per_sample, 32);
+        assert_eq!(wave.channels[0], [0.0, 0.5, -1.0, -0.25]);
+    }
+
+    #[test]
+    fn read_multichannel_data_from_wave_file() {
+        let file = get_full_path_of_test_resource("test_input_3ch.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.channels.len(), 3);
+        assert_eq!(wave.channels[0], [0.5, 0.0]);
+        assert_eq!(wave.channels[1], [0.25, 0.0]);
+        assert_eq!(wave.channels[2], [-0.75, 0.75]);
+        assert_eq!(wave.mono(), [0.0, 0.25]);
+    }
+
+    #[test]
+    fn decode_channel_out_of_range() {
+        let file = get_full_path_of_test_resource("test_input_3ch.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let header = WaveHeader::try_from(&mut file).unwrap();
+        let text = decode_samples(&header, &mut file, &ChannelSelection::Single(3));
+        assert!(matches!(text, Err(WavReadError::NoSuchChannel)));
+    }
+
+    #[test]
+    fn read_data_from_memory() {
+        let bytes = std::fs::read(get_full_path_of_test_resource("test_input_short.wav")).unwrap();
+        let mut cursor = std::io::Cursor::new(bytes);
+        let wave = WaveFile::from_reader(&mut cursor).unwrap();
+        assert_eq!(wave.header.sample_rate, Hertz(8000));
+        assert_eq!(wave.channels[0].len(), 6);
+    }
+
+    #[test]
+    fn read_data_from_stream() {
+        let bytes = std::fs::read(get_full_path_of_test_resource("test_input_info.wav")).unwrap();
+        let mut stream = bytes.as_slice();
+        let header = WaveHeader::from_stream(&mut stream).unwrap();
+        let samples: Vec<f32> = Frames::new(&header, &mut stream)
+            .unwrap()
+            .map(|frame| frame.unwrap()[0])
+            .collect();
+        let seekable = WaveFile::from_reader(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(header, seekable.header);
+        assert_eq!(samples, seekable.channels[0]);
+    }
+
+    #[test]
+    fn read_stream_needs_fmt_before_data() {
+        let bytes = std::fs::read(get_full_path_of_test_resource("test_input_no_fmt.wav")).unwrap();
+        let header = WaveHeader::from_stream(&mut bytes.as_slice());
+        assert_eq!(header_error(header), WavHeaderError::Format);
+    }
+
+    #[test]
+    fn read_aiff_file() {
+        let file = get_full_path_of_test_resource("test_input.aiff");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.riff.container, Container::Aiff);
+        assert_eq!(wave.header.riff.file_size, 64);
+        assert_eq!(wave.header.sample_rate, Hertz(8000));
+        assert_eq!(wave.header.byte_rate, Hertz(16000));
+        assert_eq!(wave.header.block_align, 2);
+        assert_eq!(wave.header.sample_frames, Some(3));
+        assert_eq!(wave.header.metadata["INAM"], "aiff");
+        assert_eq!(wave.channels[0], [0.0, 0.5, -0.5]);
+    }
+
+    #[test]
+    fn read_aifc_file() {
+        let file = get_full_path_of_test_resource("test_input.aifc");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.riff.container, Container::Aifc);
+        assert_eq!(wave.header.endianness, Endianness::Little);
+        // The samples start after the 2 bytes of offset in 'SSND'
+        assert_eq!(wave.header.data_offset, 76);
+        assert_eq!(wave.header.data_size, 8);
+        assert_eq!(wave.channels, [[0.0, -0.5], [0.5, 0.25]]);
+
+        let bytes = std::fs::read(get_full_path_of_test_resource("test_input.aifc")).unwrap();
+        let mut stream = bytes.as_slice();
+        let header = WaveHeader::from_stream(&mut stream).unwrap();
+        let frames: Vec<Vec<f32>> = Frames::new(&header, stream)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(frames, [[0.0, 0.5], [-0.5, 0.25]]);
+    }
+
+    #[test]
+    fn read_aiff_8bit_is_signed() {
+        let mut bytes = std::fs::read(get_full_path_of_test_resource("test_input.aiff")).unwrap();
+        // 8 bit samples in the 6 bytes that were 16 bit ones
+        bytes[26..28].copy_from_slice(&8u16.to_be_bytes());
+        bytes[66..72].copy_from_slice(&[0, 0x40, 0xc0, 0x80, 0x7f, 0]);
+        let wave = WaveFile::from_reader(&mut std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(wave.header.block_align, 1);
+        assert_eq!(wave.channels[0][..4], [0.0, 0.5, -0.5, -1.0]);
+    }
+
+    #[test]
+    fn aifc_compression_types() {
+        let mut bytes = std::fs::read(get_full_path_of_test_resource("test_input.aifc")).unwrap();
+        bytes[50..54].copy_from_slice(b"fl32");
+        let header = WaveHeader::from_reader(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(header.audio_format, WaveFormat::Float);
+        assert_eq!(header.block_align, 8);
+
+        bytes[50..54].copy_from_slice(b"ima4");
+        let header = WaveHeader::from_reader(&mut std::io::Cursor::new(&bytes));
+        assert_eq!(header_error(header), WavHeaderError::UnrecognisedWaveFormat);
+    }
+
+    #[test]
+    fn read_float_data_from_wave_file() {
+        let file = get_full_path_of_test_resource("test_input_float.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.audio_format, WaveFormat::Float);
+        assert_eq!(wave.header.bits_per_sample, 32);
+        assert_eq!(wave.channels[0], [0.0, 0.25, -0.5, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn read_double_data_from_wave_file() {
+        let file = get_full_path_of_test_resource("test_input_double.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.audio_format, WaveFormat::Float);
+        assert_eq!(wave.header.bits_per_sample, 64);
+        assert_eq!(wave.channels[0], [0.0, 0.25, -0.5, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn read_extensible_data_from_wave_file() {
+        let file = get_full_path_of_test_resource("test_input_extensible.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.audio_format, WaveFormat::Extensible);
+        assert_eq!(wave.header.sub_format, Some(WaveFormat::Float));
+        assert_eq!(wave.header.valid_bits_per_sample, 32);
+        assert_eq!(wave.header.channel_mask, 0x3);
+        assert_eq!(wave.header.speakers(), ["FL", "FR"]);
+        assert_eq!(wave.channels[0], [0.0, -0.5]);
+        assert_eq!(wave.channels[1], [0.25, 1.0]);
+    }
+
+    #[test]
+    fn sub_format_guids() {
+        let mut guid: [u8; 16] = [0; 16];
+        guid[4..].copy_from_slice(&SUBTYPE_GUID_TAIL);
+        guid[0] = 1;
+        assert_eq!(sub_format_from_guid(&guid).unwrap(), WaveFormat::Pcm);
+        guid[0] = 3;
+        assert_eq!(sub_format_from_guid(&guid).unwrap(), WaveFormat::Float);
+        guid[15] = 0;
+        assert_eq!(
+            header_error(sub_format_from_guid(&guid)),
+            WavHeaderError::UnrecognisedWaveFormat
+        );
+    }
+
+    #[test]
+    fn g711_expansion() {
+        assert_eq!(alaw_to_linear(0xd5), 8);
+        assert_eq!(alaw_to_linear(0x55), -8);
+        assert_eq!(alaw_to_linear(0xaa), 32256);
+        assert_eq!(alaw_to_linear(0x2a), -32256);
+        assert_eq!(mulaw_to_linear(0xff), 0);
+        assert_eq!(mulaw_to_linear(0x7f), 0);
+        assert_eq!(mulaw_to_linear(0x80), 32124);
+        assert_eq!(mulaw_to_linear(0x00), -32124);
+    }
+
+    #[test]
+    fn read_mulaw_data_from_wave_file() {
+        let file = get_full_path_of_test_resource("test_input_mulaw.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.audio_format, WaveFormat::Mulaw);
+        let expected: Vec<f32> = [0i16, 32124, -32124]
+            .iter()
+            .map(|s| f32::from(*s) / 32768.0)
+            .collect();
+        assert_eq!(wave.channels[0], expected);
+    }
+
+    #[test]
+    fn read_adpcm_data_from_wave_file() {
+        let file = get_full_path_of_test_resource("test_input_adpcm.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let wave = WaveFile::try_from(&mut file).unwrap();
+        assert_eq!(wave.header.audio_format, WaveFormat::ImaAdpcm);
+        assert_eq!(wave.header.samples_per_block, 9);
+        assert_eq!(wave.header.sample_frames, Some(12));
+        // Two blocks of 9 frames, but 'fact' says the last 6 are padding
+        let left: Vec<f32> = [
+            1000i16, 1006, 1016, 1022, 1030, 1034, 1041, 1044, 1050, 2000, 2093, 2292,
+        ]
+        .iter()
+        .map(|s| f32::from(*s) / 32768.0)
+        .collect();
+        assert_eq!(wave.channels[0], left);
+        assert_eq!(wave.channels[1][11], -2080.0 / 32768.0);
+    }
+
+    #[test]
+    fn read_adpcm_stream_without_fact() {
+        let mut bytes =
+            std::fs::read(get_full_path_of_test_resource("test_input_adpcm.wav")).unwrap();
+        // Renaming 'fact' means nothing says where the padding starts
+        bytes[40..44].copy_from_slice(b"JUNK");
+        let mut stream = bytes.as_slice();
+        let header = WaveHeader::from_stream(&mut stream).unwrap();
+        assert_eq!(header.sample_frames, None);
+        assert_eq!(Frames::new(&header, stream).unwrap().count(), 18);
+    }
+
+    #[test]
+    fn adpcm_block_align_has_to_fit_the_channels() {
+        let mut header = WaveHeader {
+            audio_format: WaveFormat::ImaAdpcm,
+            num_channels: 2,
+            bits_per_sample: 4,
+            block_align: 12,
+            ..Default::default()
+        };
+        assert!(Frames::new(&header, std::io::empty()).is_err());
+        header.block_align = 24;
+        assert_eq!(header.adpcm_block_frames().unwrap(), 17);
+        header.samples_per_block = 18;
+        assert!(header.adpcm_block_frames().is_err());
+    }
+
+    #[test]
+    fn frames_from_stereo_file() {
+        let file = get_full_path_of_test_resource("test_input_8bit.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let header = WaveHeader::try_from(&mut file).unwrap();
+        let frames: Vec<Vec<f32>> = Frames::new(&header, &mut file)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            frames,
+            [vec![0.0, -0.5], vec![0.5, 127.0 / 128.0], vec![-1.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn decode_samples_as_they_are_read() {
+        let file = get_full_path_of_test_resource("test_input.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let whole = WaveFile::try_from(&mut file).unwrap();
+
+        file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        let header = WaveHeader::try_from(&mut file).unwrap();
+        let text = decode_samples(&header, &mut file, &ChannelSelection::default()).unwrap();
+        assert_eq!(
+            text,
+            parse(
+                &whole.channels[0],
+                DEFAULT_THRESHOLD,
+                Timing::from_rate(whole.header.sample_rate.0)
+            )
+        );
+        assert!(text.trim_start().starts_with("hello!"));
+    }
+
+    // test_input.wav with a 'bext' chunk after 'fmt ', as a BWF recorder would write it
+    fn bwf_bytes() -> Vec<u8> {
+        let mut bext = vec![0; 602];
+        bext[..6].copy_from_slice(b"Beacon");
+        bext[320..338].copy_from_slice(b"2024-03-0912:00:00");
+        // A quarter of a second after midday
+        bext[338..346].copy_from_slice(&(8000_u64 * 43_200 + 2000).to_le_bytes());
+        let mut chunk = b"bext".to_vec();
+        chunk.extend_from_slice(&(bext.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(&bext);
+
+        let mut bytes = std::fs::read(get_full_path_of_test_resource("test_input.wav")).unwrap();
+        bytes.splice(36..36, chunk.iter().copied());
+        let riff_size = bytes.len() as u32 - 8;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn read_bext_chunk() {
+        let bytes = bwf_bytes();
+        let header = WaveHeader::from_reader(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert!(header.bext.is_some());
+        assert_eq!(header.timestamp(0), "2024-03-09 12:00:00.250");
+        assert!(header.to_string().contains("| Description "));
+
+        let streamed = WaveHeader::from_stream(&mut bytes.as_slice()).unwrap();
+        assert_eq!(streamed.bext, header.bext);
+    }
+
+    #[test]
+    fn letters_have_times() {
+        let bytes = bwf_bytes();
+        let mut f = std::io::Cursor::new(&bytes);
+        let header = WaveHeader::from_reader(&mut f).unwrap();
+        let letters = decode_letters(&header, &mut f, &ChannelSelection::default()).unwrap();
+        assert!(text_of(&letters).trim_start().starts_with("hello! if"));
+        assert!(letters
+            .windows(2)
+            .all(|pair| pair[0].sample < pair[1].sample));
+
+        // The recording starts a quarter of a second after midday and the 'h' 2073 samples in
+        let h = letters.iter().find(|letter| letter.text == "h").unwrap();
+        assert_eq!(h.sample, 2073);
+        assert_eq!(header.timestamp(h.sample), "2024-03-09 12:00:00.509");
+    }
+
+    #[test]
+    fn words_for_labels() {
+        let letter = |sample, text| Letter { sample, text };
+        let letters = [
+            letter(0, " "),
+            letter(10, "h"),
+            letter(20, "i"),
+            letter(30, " "),
+            letter(40, " "),
+            letter(50, "a"),
+        ];
+        assert_eq!(
+            word_labels(&letters),
+            [(10, String::from("hi")), (50, String::from("a"))]
+        );
+        assert!(word_labels(&[]).is_empty());
+    }
+
+    #[test]
+    fn decode_raw_samples() {
+        let raw = |bits, signedness, endianness| {
+            WaveHeader::from_raw(
+                &RawFormat {
+                    bits_per_sample: bits,
+                    signedness,
+                    endianness,
+                    ..Default::default()
+                },
+                UNTIL_EOF,
+            )
+        };
+        let header = raw(8, Signedness::Signed, Endianness::Little);
+        assert_eq!(header.decode_sample(&[0xc0]).unwrap(), -0.5);
+        let header = raw(16, Signedness::Unsigned, Endianness::Big);
+        assert_eq!(header.decode_sample(&[0xc0, 0x00]).unwrap(), 0.5);
+        let header = raw(24, Signedness::ByWidth, Endianness::Big);
+        assert_eq!(header.decode_sample(&[0xc0, 0x00, 0x00]).unwrap(), -0.5);
+        let header = raw(32, Signedness::ByWidth, Endianness::Little);
+        assert_eq!(header.decode_sample(&[0, 0, 0, 0x40]).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn decode_raw_input() {
+        let bytes = std::fs::read(get_full_path_of_test_resource("test_input.wav")).unwrap();
+        // The same samples with no header, big endian and offset to be unsigned
+        let samples: Vec<u8> = bytes[78..]
+            .chunks_exact(2)
+            .flat_map(|s| {
+                let s = i16::from_le_bytes([s[0], s[1]]) as u16 ^ 0x8000;
+                s.to_be_bytes().to_vec()
+            })
+            .collect();
+        let format = RawFormat {
+            signedness: Signedness::Unsigned,
+            endianness: Endianness::Big,
+            ..Default::default()
+        };
+        let header = WaveHeader::from_raw(&format, samples.len() as u64);
+        let mut wave = WaveFile::from(header);
+        wave.read_samples(&mut samples.as_slice()).unwrap();
+
+        let file = get_full_path_of_test_resource("test_input.wav");
+        let wav = WaveFile::try_from(&mut std::fs::File::open(file).unwrap()).unwrap();
+        assert_eq!(wave.channels, wav.channels);
     }
 
     #[test]
@@ -875,9 +3351,19 @@ mod tests {
         for d in &mut data[100..601] {
             *d = thresh + 1;
         }
-        let symbols = parse(&data, thresh);
+        let symbols = parse(data, thresh, Timing::from_rate(8000));
+        assert_eq!("e", symbols);
+    }
+    #[test]
+    fn parse_e_float() {
+        let mut data: [f32; 2000] = [0.0; 2000];
+        for d in &mut data[100..601] {
+            *d = -0.5;
+        }
+        let symbols = parse(data, DEFAULT_THRESHOLD, Timing::from_rate(8000));
         assert_eq!("e", symbols);
     }
+
     #[test]
     fn parse_j() {
         // Short is 60ms
@@ -901,7 +3387,7 @@ mod tests {
         for d in &mut data[6001..8000] {
             *d = thresh + 1;
         }
-        let symbols = parse(&data, thresh);
+        let symbols = parse(data, thresh, Timing::from_rate(8000));
         assert_eq!("j", symbols);
     }
 
@@ -931,7 +3417,67 @@ mod tests {
         for d in &mut data[10001..10500] {
             *d = thresh + 1;
         }
-        let symbols = parse(&data, thresh);
+        let symbols = parse(data, thresh, Timing::from_rate(8000));
         assert_eq!("j e", symbols);
     }
+
+    // Keys a tone on and off, with the durations in milliseconds
+    fn keyed(sample_rate: u32, pattern: &[(bool, f64)]) -> Vec<f32> {
+        let mut samples = Vec::new();
+        for (on, ms) in pattern {
+            let count = (ms * f64::from(sample_rate) / 1000.0) as usize;
+            let start = samples.len();
+            samples.extend((start..start + count).map(|i| match on {
+                true => (i as f32 * 2.0 * std::f32::consts::PI * 600.0 / sample_rate as f32).sin(),
+                false => 0.0,
+            }));
+        }
+        samples
+    }
+
+    #[test]
+    fn timing_at_8khz() {
+        // The sample counts everything was originally measured in
+        let timing = Timing::from_rate(8000);
+        assert_eq!(
+            timing,
+            Timing {
+                zero_crossing: 3,
+                short: 400,
+                long: 1200,
+                symbol_gap: 1000,
+                letter_gap: 2000,
+            }
+        );
+        assert_eq!(timing.symbol(400), Err(SymbolError));
+        assert_eq!(timing.symbol(401), Ok(Symbol::Short));
+        assert_eq!(timing.symbol(1201), Ok(Symbol::Long));
+        assert_eq!(Timing::from_rate(48000).long, 7200);
+    }
+
+    #[test]
+    fn parse_at_any_sample_rate() {
+        // "je" followed by "t", with a 60ms dot
+        let (dot, dash, gap) = ((true, 60.0), (true, 180.0), (false, 60.0));
+        let pattern = [
+            (false, 10.0),
+            dot,
+            gap,
+            dash,
+            gap,
+            dash,
+            gap,
+            dash,
+            (false, 180.0),
+            dot,
+            (false, 420.0),
+            dash,
+            (false, 300.0),
+        ];
+        for rate in &[8000, 11025, 22050, 44100, 48000, 96000] {
+            let samples = keyed(*rate, &pattern);
+            let text = parse(&samples, DEFAULT_THRESHOLD, Timing::from_rate(*rate));
+            assert_eq!(text, "je t", "at {}Hz", rate);
+        }
+    }
 }
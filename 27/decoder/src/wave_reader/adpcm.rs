@@ -0,0 +1,178 @@
+use super::{normalise, WavHeaderError, WavReadError, WaveHeader};
+
+// IMA/DVI ADPCM, as per the IMA Digital Audio Focus and Technical Working Groups
+// recommended practices. Each 4 bit code moves the prediction by a step that grows or shrinks
+// depending on how big the last change was
+const INDEX_TABLE: [i8; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+// Every channel starts a block with a 4 byte header, then the codes come in groups of
+// 4 bytes, 8 samples, for each channel in turn
+const HEADER_LEN: usize = 4;
+const GROUP_LEN: usize = 4;
+
+#[derive(Debug, Default)]
+struct Channel {
+    predictor: i32,
+    step_index: usize,
+}
+
+impl Channel {
+    fn from_header(header: &[u8]) -> Self {
+        Channel {
+            predictor: i16::from_le_bytes([header[0], header[1]]).into(),
+            // Anything out of range would index past the table, so it's treated as the largest step
+            step_index: usize::from(header[2]).min(STEP_TABLE.len() - 1),
+        }
+    }
+
+    fn decode(&mut self, code: u8) -> i16 {
+        let step = STEP_TABLE[self.step_index];
+        let mut diff = step >> 3;
+        if code & 1 != 0 {
+            diff += step >> 2;
+        }
+        if code & 2 != 0 {
+            diff += step >> 1;
+        }
+        if code & 4 != 0 {
+            diff += step;
+        }
+        if code & 8 != 0 {
+            diff = -diff;
+        }
+        self.predictor = (self.predictor + diff).clamp(i16::MIN.into(), i16::MAX.into());
+
+        let index = self.step_index as i32 + i32::from(INDEX_TABLE[usize::from(code)]);
+        self.step_index = index.clamp(0, STEP_TABLE.len() as i32 - 1) as usize;
+        self.predictor as i16
+    }
+}
+
+impl WaveHeader {
+    // How many frames each block decodes to, after checking that the blocks can be split up
+    // between the channels the way IMA ADPCM needs
+    pub(super) fn adpcm_block_frames(&self) -> Result<usize, WavReadError> {
+        if self.bits_per_sample != 4 {
+            return Err(self.format_error(
+                WavHeaderError::BitsPerSample,
+                &self.file_bytes(self.bits_per_sample),
+            ));
+        }
+
+        let block_align = usize::from(self.block_align);
+        let channels = usize::from(self.num_channels);
+        let bad_block_align = || {
+            self.format_error(
+                WavHeaderError::BlockAlign,
+                &self.file_bytes(self.block_align),
+            )
+        };
+        if channels == 0
+            || block_align <= HEADER_LEN * channels
+            || !(block_align - HEADER_LEN * channels).is_multiple_of(GROUP_LEN * channels)
+        {
+            return Err(bad_block_align());
+        }
+
+        // The sample in each header counts as a frame too
+        let frames = (block_align - HEADER_LEN * channels) * 2 / channels + 1;
+        match usize::from(self.samples_per_block) {
+            // Left out of some files, in which case the block is assumed to be full
+            0 => Ok(frames),
+            given if given <= frames => Ok(given),
+            _ => Err(bad_block_align()),
+        }
+    }
+}
+
+// Decodes as much of a block as there is, as the last one in a file can be cut short. The
+// frames have a normalised sample for each channel, same as the other formats
+pub(super) fn decode_block(block: &[u8], channels: usize) -> Vec<Vec<f32>> {
+    if channels == 0 || block.len() < HEADER_LEN * channels {
+        return Vec::new();
+    }
+
+    let (headers, codes) = block.split_at(HEADER_LEN * channels);
+    let mut states: Vec<Channel> = headers
+        .chunks_exact(HEADER_LEN)
+        .map(Channel::from_header)
+        .collect();
+    let mut frames = vec![states
+        .iter()
+        .map(|s| s.predictor as i16)
+        .collect::<Vec<i16>>()];
+
+    for group in codes.chunks_exact(GROUP_LEN * channels) {
+        let mut decoded = vec![Vec::with_capacity(GROUP_LEN * 2); channels];
+        for ((state, bytes), samples) in states
+            .iter_mut()
+            .zip(group.chunks_exact(GROUP_LEN))
+            .zip(decoded.iter_mut())
+        {
+            // The low nibble is the earlier sample
+            for byte in bytes {
+                samples.push(state.decode(byte & 0x0f));
+                samples.push(state.decode(byte >> 4));
+            }
+        }
+        for i in 0..GROUP_LEN * 2 {
+            frames.push(decoded.iter().map(|samples| samples[i]).collect());
+        }
+    }
+
+    frames
+        .iter()
+        .map(|frame| frame.iter().copied().map(normalise).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_i16(frames: Vec<Vec<f32>>) -> Vec<Vec<i16>> {
+        frames
+            .iter()
+            .map(|frame| frame.iter().map(|s| (s * 32768.0) as i16).collect())
+            .collect()
+    }
+
+    #[test]
+    fn decode_mono_block() {
+        // Predictor 100, step index 0, then codes 4, 0, 15, 8
+        let block = [100, 0, 0, 0, 0x04, 0x8f, 0x00, 0x00];
+        let frames = to_i16(decode_block(&block, 1));
+        let samples: Vec<i16> = frames.iter().map(|frame| frame[0]).collect();
+        // +7 on a step of 7, then +1 on 9, -15 on 8 and -2 on 17
+        assert_eq!(samples[..5], [100, 107, 108, 93, 91]);
+        assert_eq!(samples.len(), 9);
+    }
+
+    #[test]
+    fn decode_stereo_groups() {
+        let mut block = vec![0, 0, 0, 0, 0xf4, 0xff, 88, 0];
+        block.extend_from_slice(&[0x44; 4]);
+        block.extend_from_slice(&[0xff; 4]);
+        let frames = to_i16(decode_block(&block, 2));
+        assert_eq!(frames.len(), 9);
+        assert_eq!(frames[0], [0, -12]);
+        // The right channel starts on the biggest step so the first code takes it to the limit
+        assert_eq!(frames[1], [7, i16::MIN]);
+        assert!(frames[2..].iter().all(|frame| frame[0] > frames[1][0]));
+    }
+
+    #[test]
+    fn decode_short_blocks() {
+        assert!(decode_block(&[0, 0, 0], 1).is_empty());
+        // A partial group of codes can't be split between the channels
+        assert_eq!(decode_block(&[0; 10], 2).len(), 1);
+    }
+}
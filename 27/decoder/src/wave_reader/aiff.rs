@@ -0,0 +1,195 @@
+use std::convert::TryInto;
+use std::io::Read;
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+use super::{
+    Container, Endianness, Hertz, Signedness, WavHeaderError, WavReadError, WaveFormat, WaveHeader,
+};
+
+// AIFF text chunks and the INFO ids they're shown as
+const TEXT_CHUNKS: [(&[u8; 4], &str); 4] = [
+    (b"NAME", "INAM"),
+    (b"AUTH", "IART"),
+    (b"(c) ", "ICOP"),
+    (b"ANNO", "ICMT"),
+];
+
+pub(super) fn is_text_chunk(id: &[u8; 4]) -> bool {
+    TEXT_CHUNKS.iter().any(|(text_id, _)| *text_id == id)
+}
+
+// The sample rate is an 80 bit IEEE 754 extended float: a sign bit, a 15 bit exponent and
+// a 64 bit mantissa that, unlike f64, keeps its leading 1
+fn extended_to_f64(bytes: &[u8; 10]) -> f64 {
+    let sign_exponent = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let mantissa = u64::from_be_bytes(bytes[2..].try_into().unwrap());
+    let exponent = i32::from(sign_exponent & 0x7fff);
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    let value = mantissa as f64 * 2.0_f64.powi(exponent - 16383 - 63);
+    match sign_exponent & 0x8000 {
+        0 => value,
+        _ => -value,
+    }
+}
+
+impl WaveHeader {
+    // 'COMM' holds the same things as 'fmt ', less the rates and alignment which are always
+    // what the sample size makes them. AIFF-C adds a compression type and a name for it
+    pub(super) fn read_common_chunk<R: Read>(
+        &mut self,
+        f: &mut R,
+        comm_len: u64,
+    ) -> Result<(), WavReadError> {
+        let needed = match self.riff.container {
+            Container::Aifc => 22,
+            _ => 18,
+        };
+        if comm_len < needed {
+            return Err(WavHeaderError::FormatLength.into());
+        }
+
+        self.num_channels = f.read_u16::<BigEndian>()?;
+        self.sample_frames = Some(f.read_u32::<BigEndian>()?);
+        self.bits_per_sample = f.read_u16::<BigEndian>()?;
+        let mut rate: [u8; 10] = [0; 10];
+        f.read_exact(&mut rate)?;
+        // Saturates, so nonsense such as a negative rate comes out as 0
+        self.sample_rate = Hertz(extended_to_f64(&rate).round() as u32);
+
+        // AIFF samples are always signed, even 8 bit ones
+        self.audio_format = WaveFormat::Pcm;
+        self.signedness = Signedness::Signed;
+        self.endianness = Endianness::Big;
+        if self.riff.container == Container::Aifc {
+            let mut compression: [u8; 4] = [0; 4];
+            f.read_exact(&mut compression)?;
+            match &compression {
+                b"NONE" | b"twos" => {}
+                b"sowt" => self.endianness = Endianness::Little,
+                b"fl32" | b"FL32" => {
+                    self.audio_format = WaveFormat::Float;
+                    self.bits_per_sample = 32;
+                }
+                b"fl64" | b"FL64" => {
+                    self.audio_format = WaveFormat::Float;
+                    self.bits_per_sample = 64;
+                }
+                // The sample size is what they expand to, there's only a byte of each stored
+                b"alaw" | b"ALAW" => {
+                    self.audio_format = WaveFormat::Alaw;
+                    self.bits_per_sample = 8;
+                }
+                b"ulaw" | b"ULAW" => {
+                    self.audio_format = WaveFormat::Mulaw;
+                    self.bits_per_sample = 8;
+                }
+                _ => {
+                    return Err(WavReadError::from(WavHeaderError::UnrecognisedWaveFormat)
+                        .found(&compression))
+                }
+            }
+        }
+
+        // Nothing in the chunk limits these, so a corrupt one can ask for more than fits
+        self.block_align = self
+            .num_channels
+            .checked_mul(self.bits_per_sample.div_ceil(8))
+            .ok_or_else(|| {
+                WavReadError::from(WavHeaderError::BlockAlign)
+                    .found(&self.num_channels.to_be_bytes())
+            })?;
+        self.byte_rate = Hertz(
+            self.sample_rate
+                .0
+                .checked_mul(u32::from(self.block_align))
+                .ok_or_else(|| WavReadError::from(WavHeaderError::BlockAlign).found(&rate))?,
+        );
+        Ok(())
+    }
+
+    // The NAME, AUTH, (c) and ANNO chunks, which are plain text with nothing else in them
+    pub(super) fn read_text_chunk(&mut self, id: &[u8; 4], contents: &[u8]) {
+        if let Some((_, info_id)) = TEXT_CHUNKS.iter().find(|(text_id, _)| *text_id == id) {
+            let value = String::from_utf8_lossy(contents)
+                .trim_end_matches('\0')
+                .to_owned();
+            // There can be any number of annotations
+            self.metadata
+                .entry(String::from(*info_id))
+                .and_modify(|existing| {
+                    existing.push_str("; ");
+                    existing.push_str(&value);
+                })
+                .or_insert(value);
+        }
+    }
+}
+
+// 'SSND' starts with an offset to the first sample and a block size that's only there for
+// alignment. Gives back how many bytes come before the samples
+pub(super) fn read_sound_header<R: Read>(f: &mut R) -> Result<u64, WavReadError> {
+    let offset = f.read_u32::<BigEndian>()?;
+    let _block_size = f.read_u32::<BigEndian>()?;
+    std::io::copy(&mut f.take(offset.into()), &mut std::io::sink())?;
+    Ok(8 + u64::from(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extended_sample_rates() {
+        let rate = |bytes: [u8; 10]| extended_to_f64(&bytes);
+        assert_eq!(rate([0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]), 44100.0);
+        assert_eq!(rate([0x40, 0x0b, 0xfa, 0, 0, 0, 0, 0, 0, 0]), 8000.0);
+        assert_eq!(rate([0; 10]), 0.0);
+        assert_eq!(rate([0xc0, 0x0b, 0xfa, 0, 0, 0, 0, 0, 0, 0]), -8000.0);
+    }
+
+    #[test]
+    fn text_chunks_as_info() {
+        let mut header = WaveHeader::default();
+        header.read_text_chunk(b"NAME", b"Beacon\0");
+        header.read_text_chunk(b"ANNO", b"first");
+        header.read_text_chunk(b"ANNO", b"second");
+        header.read_text_chunk(b"APPL", b"ignored");
+        assert_eq!(header.metadata["INAM"], "Beacon");
+        assert_eq!(header.metadata["ICMT"], "first; second");
+        assert_eq!(header.metadata.len(), 2);
+    }
+
+    fn common_chunk(channels: u16, bits: u16, rate: [u8; 10]) -> Result<(), WavReadError> {
+        let mut comm = Vec::new();
+        comm.extend_from_slice(&channels.to_be_bytes());
+        comm.extend_from_slice(&1000u32.to_be_bytes());
+        comm.extend_from_slice(&bits.to_be_bytes());
+        comm.extend_from_slice(&rate);
+        let mut header = WaveHeader::default();
+        header.riff.container = Container::Aiff;
+        header.read_common_chunk(&mut comm.as_slice(), 18)
+    }
+
+    #[test]
+    fn common_chunk_overflow() {
+        let rate_44100 = [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0];
+        assert!(common_chunk(2, 16, rate_44100).is_ok());
+        match common_chunk(40000, 32, rate_44100) {
+            Err(WavReadError::Header(WavHeaderError::BlockAlign, context)) => {
+                assert_eq!(context.found, 40000u16.to_be_bytes())
+            }
+            other => panic!("Expected a block align error, got {:?}", other),
+        }
+        // 4,000,000,000 Hz
+        let rate_4g = [0x40, 0x1e, 0xee, 0x6b, 0x28, 0, 0, 0, 0, 0];
+        match common_chunk(2, 32, rate_4g) {
+            Err(WavReadError::Header(WavHeaderError::BlockAlign, context)) => {
+                assert_eq!(context.found, rate_4g)
+            }
+            other => panic!("Expected a block align error, got {:?}", other),
+        }
+    }
+}
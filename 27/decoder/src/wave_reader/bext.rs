@@ -0,0 +1,220 @@
+use std::convert::TryInto;
+
+use prettytable::{Row, Table};
+
+use super::WaveHeader;
+
+// Everything up to the UMID, which is as much as the timestamps need. Later versions only
+// add things after it
+const MIN_LEN: usize = 348;
+
+// The Broadcast Audio Extension chunk, EBU Tech 3285. Only the text fields and the time
+// reference are kept
+#[derive(PartialEq, Debug, Default, Clone)]
+pub(super) struct Bext {
+    description: String,
+    originator: String,
+    originator_reference: String,
+    // yyyy-mm-dd and hh:mm:ss, though the separators can be anything
+    origination_date: String,
+    origination_time: String,
+    // Samples since midnight on the origination date when the recording started
+    time_reference: u64,
+    coding_history: String,
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches('\0')
+        .trim_end()
+        .to_owned()
+}
+
+impl Bext {
+    // Too short to hold the time reference is treated the same as not being there
+    pub(super) fn from_bytes(contents: &[u8]) -> Option<Self> {
+        if contents.len() < MIN_LEN {
+            return None;
+        }
+        let low = u32::from_le_bytes(contents[338..342].try_into().unwrap());
+        let high = u32::from_le_bytes(contents[342..346].try_into().unwrap());
+        Some(Bext {
+            description: text(&contents[..256]),
+            originator: text(&contents[256..288]),
+            originator_reference: text(&contents[288..320]),
+            origination_date: text(&contents[320..330]),
+            origination_time: text(&contents[330..338]),
+            time_reference: u64::from(high) << 32 | u64::from(low),
+            coding_history: contents.get(602..).map(text).unwrap_or_default(),
+        })
+    }
+
+    // Days since 1970-01-01 for the origination date, if it's a real date
+    fn origination_day(&self) -> Option<i64> {
+        let date = self.origination_date.as_bytes();
+        if date.len() != 10 {
+            return None;
+        }
+        let year = number(&date[0..4])?;
+        let month = number(&date[5..7])?;
+        let day = number(&date[8..10])?;
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+        Some(days_from_civil(year, month, day))
+    }
+
+    pub(super) fn add_rows(&self, tbl: &mut Table) {
+        let rows = [
+            ("Description", &self.description),
+            ("Originator", &self.originator),
+            ("Originator Reference", &self.originator_reference),
+            ("Coding History", &self.coding_history),
+        ];
+        for (name, value) in rows.iter().filter(|(_, value)| !value.is_empty()) {
+            tbl.add_row(Row::from(vec![*name, value.as_str()]));
+        }
+        tbl.add_row(Row::from(vec![
+            "Origination",
+            &format!("{} {}", self.origination_date, self.origination_time),
+        ]));
+        tbl.add_row(Row::from(vec![
+            "Time Reference",
+            &format!("{} samples", self.time_reference),
+        ]));
+    }
+}
+
+fn number(digits: &[u8]) -> Option<i64> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+// Howard Hinnant's days_from_civil and civil_from_days, for the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn time_of_day(ms: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+impl WaveHeader {
+    // When a frame was recorded. BWF files give the wall clock time from 'bext', anything
+    // else the time since the start of the recording
+    pub(super) fn timestamp(&self, frame: u64) -> String {
+        let rate = u64::from(self.sample_rate.0.max(1));
+        // The time reference is whatever the file says it is, so it can be too big to add to.
+        // Then it's no use as a clock and the time since the start is all that can be shown
+        let start = self.bext.as_ref().and_then(|bext| {
+            let day = bext.origination_day()?;
+            let ms = bext.time_reference.checked_add(frame)?.checked_mul(1000)? / rate;
+            Some((day, ms))
+        });
+        match start {
+            Some((day, ms)) => {
+                let (year, month, day) = civil_from_days(day + (ms / 86_400_000) as i64);
+                format!(
+                    "{:04}-{:02}-{:02} {}",
+                    year,
+                    month,
+                    day,
+                    time_of_day(ms % 86_400_000)
+                )
+            }
+            None => {
+                let ms = u128::from(frame) * 1000 / u128::from(rate);
+                time_of_day(ms.try_into().unwrap_or(u64::MAX))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bext_bytes(date: &[u8], time: &[u8], time_reference: u64) -> Vec<u8> {
+        let mut bytes = vec![0; 602];
+        bytes[..6].copy_from_slice(b"Beacon");
+        bytes[256..261].copy_from_slice(b"G4XYZ");
+        bytes[320..330].copy_from_slice(date);
+        bytes[330..338].copy_from_slice(time);
+        bytes[338..346].copy_from_slice(&time_reference.to_le_bytes());
+        bytes.extend_from_slice(b"A=PCM,F=8000,W=16,M=mono\r\n");
+        bytes
+    }
+
+    #[test]
+    fn bext_fields() {
+        let bext = Bext::from_bytes(&bext_bytes(b"2024-03-09", b"23:59:58", 1 << 33)).unwrap();
+        assert_eq!(bext.description, "Beacon");
+        assert_eq!(bext.originator, "G4XYZ");
+        assert_eq!(bext.originator_reference, "");
+        assert_eq!(bext.origination_time, "23:59:58");
+        assert_eq!(bext.time_reference, 1 << 33);
+        assert_eq!(bext.coding_history, "A=PCM,F=8000,W=16,M=mono");
+        assert_eq!(bext.origination_day(), Some(19791));
+
+        assert_eq!(Bext::from_bytes(&[0; MIN_LEN - 1]), None);
+    }
+
+    #[test]
+    fn calendar() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        for days in &[-1, 0, 11016, 11017, 19791, 20000] {
+            let (y, m, d) = civil_from_days(*days);
+            assert_eq!(days_from_civil(y, m, d), *days);
+        }
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn timestamps() {
+        let mut header = WaveHeader::new(super::super::WaveFormat::Pcm, 1, 8000, 16);
+        assert_eq!(header.timestamp(8000 * 61 + 4), "00:01:01.000");
+
+        // Half a second before midnight, so a second in it's the next day
+        let time_reference = 8000 * 86_400 - 4000;
+        let bytes = bext_bytes(b"2024-02-28", b"23:59:59", time_reference);
+        header.bext = Bext::from_bytes(&bytes);
+        assert_eq!(header.timestamp(0), "2024-02-28 23:59:59.500");
+        assert_eq!(header.timestamp(8000), "2024-02-29 00:00:00.500");
+
+        // Without a date there's nothing to count from
+        let bytes = bext_bytes(b"unknown   ", b"23:59:59", time_reference);
+        header.bext = Bext::from_bytes(&bytes);
+        assert_eq!(header.timestamp(8000), "00:00:01.000");
+
+        // A time reference too big to count on from leaves the time since the start
+        let bytes = bext_bytes(b"2024-02-28", b"23:59:59", u64::MAX - 4000);
+        header.bext = Bext::from_bytes(&bytes);
+        assert_eq!(header.timestamp(8000), "00:00:01.000");
+        assert_eq!(header.timestamp(u64::MAX), "640511947003:48:13.951");
+    }
+}
@@ -0,0 +1,229 @@
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{Container, Signedness, WavReadError, WaveFormat, WaveHeader, UNTIL_EOF};
+
+pub(super) const MAGIC: &[u8; 4] = b"fLaC";
+
+// Vorbis comments and the INFO ids they're shown as
+const TAGS: [(&str, &str); 7] = [
+    ("TITLE", "INAM"),
+    ("ARTIST", "IART"),
+    ("DATE", "ICRD"),
+    ("COMMENT", "ICMT"),
+    ("GENRE", "IGNR"),
+    ("COPYRIGHT", "ICOP"),
+    ("ENCODER", "ISFT"),
+];
+
+// Rewinds afterwards, so the file can be read from the start whatever it turns out to be
+pub(super) fn has_magic<R: Read + Seek>(f: &mut R) -> std::io::Result<bool> {
+    let mut magic = Vec::new();
+    f.by_ref()
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    f.seek(SeekFrom::Start(0))?;
+    Ok(magic == MAGIC)
+}
+
+// The input with the bytes already looked at put back in front of it
+pub(super) type Peeked<R> = std::io::Chain<std::io::Cursor<Vec<u8>>, R>;
+
+// A pipe can't be rewound and may hand over fewer bytes a read than asked for, so this
+// keeps reading until it has the whole magic or the input ends
+pub(super) fn peek_magic<R: Read>(mut f: R) -> std::io::Result<(bool, Peeked<R>)> {
+    let mut magic = Vec::new();
+    f.by_ref()
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    Ok((magic == MAGIC, std::io::Cursor::new(magic).chain(f)))
+}
+
+// Anything wrong with the stream itself comes back as invalid data, so that it's reported
+// the same way whether it's found in the header or half way through the samples
+fn to_io_error(e: claxon::Error) -> std::io::Error {
+    match e {
+        claxon::Error::IoError(e) => e,
+        e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+    }
+}
+
+// Gives the header and the samples as the PCM the rest of the decoder expects, decoding a
+// FLAC block at a time as they're read
+pub(super) fn open<R: Read>(f: R) -> Result<(WaveHeader, FlacPcm<R>), WavReadError> {
+    let reader = claxon::FlacReader::new(f).map_err(to_io_error)?;
+    let info = reader.streaminfo();
+    let bits = info.bits_per_sample as u16;
+    let mut header = WaveHeader::new(
+        WaveFormat::Pcm,
+        info.channels as u16,
+        info.sample_rate,
+        bits,
+    );
+    header.riff.container = Container::Flac;
+    // Even 8 bit FLAC samples are signed
+    header.signedness = Signedness::Signed;
+    header.sample_frames = info.samples.and_then(|frames| u32::try_from(frames).ok());
+    header.data_size = info
+        .samples
+        .map_or(UNTIL_EOF, |frames| frames * u64::from(header.block_align));
+    for (name, value) in reader.tags() {
+        if let Some((_, id)) = TAGS.iter().find(|(tag, _)| tag.eq_ignore_ascii_case(name)) {
+            header
+                .metadata
+                .insert(String::from(*id), String::from(value));
+        }
+    }
+
+    let width = usize::from(bits.div_ceil(8));
+    let pcm = FlacPcm {
+        reader,
+        buffer: Vec::new(),
+        bytes: Vec::new(),
+        position: 0,
+        width,
+        // Samples that don't fill their bytes are padded at the bottom, the same as in WAV files
+        shift: (width * 8) as u32 - u32::from(bits),
+    };
+    Ok((header, pcm))
+}
+
+pub(super) struct FlacPcm<R: Read> {
+    reader: claxon::FlacReader<R>,
+    // Kept between blocks so claxon doesn't have to allocate for each one
+    buffer: Vec<i32>,
+    // The current block as interleaved little endian samples, and how much has been read
+    bytes: Vec<u8>,
+    position: usize,
+    width: usize,
+    shift: u32,
+}
+
+impl<R: Read> Read for FlacPcm<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.bytes.len() {
+            let buffer = std::mem::take(&mut self.buffer);
+            let block = match self.reader.blocks().read_next_or_eof(buffer) {
+                Ok(Some(block)) => block,
+                Ok(None) => return Ok(0),
+                Err(e) => return Err(to_io_error(e)),
+            };
+            self.bytes.clear();
+            self.position = 0;
+            for i in 0..block.duration() {
+                for channel in 0..block.channels() {
+                    let sample = block.sample(channel, i) << self.shift;
+                    self.bytes
+                        .extend_from_slice(&sample.to_le_bytes()[..self.width]);
+                }
+            }
+            self.buffer = block.into_buffer();
+        }
+
+        let available = &self.bytes[self.position..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Vec<u8> {
+        let file = super::super::tests::get_full_path_of_test_resource("test_input.flac");
+        std::fs::read(file).unwrap()
+    }
+
+    #[test]
+    fn flac_header() {
+        let bytes = fixture();
+        let (header, _) = open(bytes.as_slice()).unwrap();
+        assert_eq!(header.riff.container, Container::Flac);
+        assert_eq!(
+            (
+                header.num_channels,
+                header.sample_rate.0,
+                header.bits_per_sample
+            ),
+            (2, 8000, 16)
+        );
+        assert_eq!(header.block_align, 4);
+        assert_eq!(header.sample_frames, Some(20));
+        assert_eq!(header.data_size, 80);
+        assert_eq!(header.metadata["INAM"], "flac");
+        assert_eq!(header.metadata["IART"], "G4XYZ");
+    }
+
+    #[test]
+    fn flac_samples_across_blocks() {
+        let bytes = fixture();
+        let (header, mut pcm) = open(bytes.as_slice()).unwrap();
+        let mut samples = Vec::new();
+        pcm.read_to_end(&mut samples).unwrap();
+        assert_eq!(samples.len() as u64, header.data_size);
+
+        let frames: Vec<Vec<f32>> = super::super::Frames::new(&header, samples.as_slice())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(frames.len(), 20);
+        assert_eq!(frames[0], [0.0, 0.5]);
+        assert_eq!(frames[2], [32767.0 / 32768.0, -1.0]);
+        assert_eq!(frames[16], [100.0 / 32768.0, -100.0 / 32768.0]);
+    }
+
+    #[test]
+    fn flac_magic() {
+        let mut f = std::io::Cursor::new(fixture());
+        assert!(has_magic(&mut f).unwrap());
+        assert_eq!(f.position(), 0);
+        assert!(!has_magic(&mut std::io::Cursor::new(b"RIFF".to_vec())).unwrap());
+        assert!(!has_magic(&mut std::io::Cursor::new(b"fL".to_vec())).unwrap());
+    }
+
+    // Hands over a byte at a time, the way a slow pipe can
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn flac_magic_in_pieces() {
+        let bytes = fixture();
+        let (is_flac, mut rest) = peek_magic(Trickle(&bytes)).unwrap();
+        assert!(is_flac);
+        let mut read_back = Vec::new();
+        rest.read_to_end(&mut read_back).unwrap();
+        assert_eq!(read_back, bytes);
+
+        let (is_flac, mut rest) = peek_magic(Trickle(b"fL")).unwrap();
+        assert!(!is_flac);
+        let mut read_back = Vec::new();
+        rest.read_to_end(&mut read_back).unwrap();
+        assert_eq!(read_back, b"fL");
+    }
+
+    #[test]
+    fn flac_damaged_stream() {
+        let mut bytes = fixture();
+        // Changes a sample in the last frame so that its CRC no longer matches
+        let last = bytes.len() - 3;
+        bytes[last] ^= 0xff;
+        let (header, pcm) = open(bytes.as_slice()).unwrap();
+        let result: Result<Vec<Vec<f32>>, _> =
+            super::super::Frames::new(&header, pcm).unwrap().collect();
+        assert!(matches!(
+            result,
+            Err(WavReadError::FileIO(ref e, _)) if e.kind() == std::io::ErrorKind::InvalidData
+        ));
+    }
+}
@@ -0,0 +1,564 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+
+use prettytable::{Row, Table};
+
+use super::{
+    aiff, read_chunk_contents, seek_within, ChunkWalker, RiffHeader, WavHeaderError, WavReadError,
+    WaveFormat, WaveHeader,
+};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(super) enum Severity {
+    // The header disagrees with itself or with the file, so it can't be trusted
+    Error,
+    // Unusual, or a slip that doesn't change how the samples are read
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub(super) struct Finding {
+    severity: Severity,
+    offset: Option<u64>,
+    message: String,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Report {
+    findings: Vec<Finding>,
+}
+
+impl Report {
+    fn add(&mut self, severity: Severity, offset: Option<u64>, message: String) {
+        self.findings.push(Finding {
+            severity,
+            offset,
+            message,
+        });
+    }
+
+    fn error(&mut self, offset: Option<u64>, message: String) {
+        self.add(Severity::Error, offset, message);
+    }
+
+    fn warning(&mut self, offset: Option<u64>, message: String) {
+        self.add(Severity::Warning, offset, message);
+    }
+
+    // Header errors are findings like any other, anything else means the file couldn't be read
+    fn header_error(&mut self, e: WavReadError) -> Result<(), WavReadError> {
+        match e {
+            WavReadError::Header(kind, context) => {
+                let offset = context.offset;
+                let message: &'static str = (&kind).into();
+                self.error(offset, format!("{}{}", message, context));
+                Ok(())
+            }
+            WavReadError::FileIO(ref io, ref context)
+                if io.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                let offset = context.offset;
+                self.error(offset, format!("The file ended early{}", context));
+                Ok(())
+            }
+            e => Err(e),
+        }
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    }
+
+    pub(super) fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub(super) fn to_json(&self) -> String {
+        let findings: Vec<String> = self
+            .findings
+            .iter()
+            .map(|finding| {
+                format!(
+                    "{{\"severity\":\"{}\",\"offset\":{},\"message\":{}}}",
+                    finding.severity,
+                    finding
+                        .offset
+                        .map_or(String::from("null"), |o| o.to_string()),
+                    json_string(&finding.message)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"errors\":{},\"warnings\":{},\"findings\":[{}]}}",
+            self.errors(),
+            self.count(Severity::Warning),
+            findings.join(",")
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.findings.is_empty() {
+            return write!(f, "No problems found");
+        }
+
+        let mut tbl = Table::new();
+        tbl.set_titles(Row::from(vec!["Severity", "Offset", "Problem"]));
+        for finding in &self.findings {
+            tbl.add_row(Row::from(vec![
+                finding.severity.to_string(),
+                finding.offset.map_or(String::new(), |o| o.to_string()),
+                finding.message.clone(),
+            ]));
+        }
+        write!(f, "{}", tbl)
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct LintFailed(pub(super) usize);
+
+impl fmt::Display for LintFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Found {} error(s) in the WAV file", self.0)
+    }
+}
+
+impl Error for LintFailed {}
+
+fn chunk_name(id: &[u8; 4]) -> String {
+    String::from_utf8_lossy(id).into_owned()
+}
+
+// Unlike reading the header, this carries on past problems so everything wrong with the file
+// is reported at once
+pub(super) fn lint<R: Read + Seek>(f: &mut R) -> Result<Report, WavReadError> {
+    let mut report = Report::default();
+    let file_len = f.seek(SeekFrom::End(0))?;
+    f.seek(SeekFrom::Start(0))?;
+
+    let riff = match RiffHeader::from_reader(f) {
+        Ok(riff) => riff,
+        Err(e) => {
+            report.header_error(e)?;
+            return Ok(report);
+        }
+    };
+    let container = riff.container;
+    let mut header = WaveHeader::from(riff);
+
+    let mut walker = ChunkWalker::new(container);
+    let mut format_ok = false;
+    let mut data = None;
+    let mut missing_pad = false;
+    loop {
+        seek_within(f, walker.offset)?;
+        let chunk = match walker.next_chunk(f)? {
+            Some(chunk) => chunk,
+            None => break,
+        };
+        let name = chunk_name(&chunk.id);
+
+        // Lists, and annotations in AIFF files, can be repeated
+        let repeatable = matches!(&chunk.id, b"LIST" | b"ANNO");
+        if !repeatable && header.find_chunk(&chunk.id).is_some() {
+            let message = format!("There's more than one '{}' chunk", name);
+            match &chunk.id {
+                id if id == container.format_id() || id == container.data_id() => {
+                    report.error(Some(chunk.offset), message)
+                }
+                _ => report.warning(Some(chunk.offset), message),
+            }
+        }
+        header.chunks.push(chunk.clone());
+
+        let available = file_len.saturating_sub(chunk.data_offset());
+        if chunk.length > available {
+            report.error(
+                Some(chunk.offset),
+                format!(
+                    "The '{}' chunk should be {} bytes but the file only has {}",
+                    name, chunk.length, available
+                ),
+            );
+            // Everything else in the file is inside this chunk
+            break;
+        }
+        if chunk.end() > file_len {
+            missing_pad = true;
+            report.warning(
+                Some(chunk.data_offset() + chunk.length),
+                format!("The '{}' chunk is missing its pad byte", name),
+            );
+        }
+
+        match &chunk.id {
+            b"ds64" if container.has_ds64() => match walker.read_ds64(f, &chunk) {
+                Ok(size) => header.riff.file_size = size,
+                Err(e) => report.header_error(e)?,
+            },
+            id if id == container.format_id() && !format_ok => {
+                let contents = read_chunk_contents(f, &chunk)?;
+                match header.read_format_chunk(&mut contents.as_slice(), chunk.length) {
+                    Ok(()) => format_ok = true,
+                    Err(e) => report.header_error(e.in_chunk(&chunk))?,
+                }
+            }
+            id if id == container.data_id() && data.is_none() => {
+                if header.find_chunk(container.format_id()).is_none() {
+                    report.warning(
+                        Some(chunk.offset),
+                        format!(
+                            "'{}' comes before '{}' so the file can't be streamed",
+                            name,
+                            chunk_name(container.format_id())
+                        ),
+                    );
+                }
+                data = Some(chunk);
+            }
+            _ => {}
+        }
+    }
+
+    if walker.offset < file_len {
+        report.warning(
+            Some(walker.offset),
+            format!(
+                "There are {} bytes after the last chunk",
+                file_len - walker.offset
+            ),
+        );
+    }
+
+    // A size that was never filled in after streaming is as wrong as any other. Only a pad
+    // byte missing from the end is let off, as that's already been reported
+    let declared_len = header.riff.declared_len();
+    if declared_len != file_len && !(missing_pad && declared_len == file_len + 1) {
+        report.error(
+            Some(4),
+            format!(
+                "The RIFF header says the file is {} bytes but it's {}",
+                declared_len, file_len
+            ),
+        );
+    }
+
+    if header.find_chunk(container.format_id()).is_none() {
+        report.header_error(WavHeaderError::Format.into())?;
+    }
+    if header.find_chunk(container.data_id()).is_none() {
+        report.header_error(WavHeaderError::Data.into())?;
+    }
+    if format_ok {
+        let data_len = match &data {
+            // Only the samples after the header at the start of 'SSND' make up frames
+            Some(data) if container.is_aiff() => {
+                f.seek(SeekFrom::Start(data.data_offset()))?;
+                match aiff::read_sound_header(f) {
+                    Ok(skipped) => Some(data.length.saturating_sub(skipped)),
+                    Err(e) => {
+                        report.header_error(e.in_chunk(data))?;
+                        None
+                    }
+                }
+            }
+            data => data.as_ref().map(|d| d.length),
+        };
+        lint_format(&header, data_len, &mut report)?;
+    }
+    Ok(report)
+}
+
+// Checks that the fields of 'fmt ' agree with each other and with the data
+fn lint_format(
+    header: &WaveHeader,
+    data_len: Option<u64>,
+    report: &mut Report,
+) -> Result<(), WavReadError> {
+    if header.sample_format() == &WaveFormat::ImaAdpcm {
+        return lint_adpcm_format(header, report);
+    }
+    let offset = header
+        .find_chunk(header.riff.container.format_id())
+        .map(|fmt| fmt.offset);
+
+    let block_align = u64::from(header.block_align);
+    let expected_rate = u64::from(header.sample_rate.0) * block_align;
+    if u64::from(header.byte_rate.0) != expected_rate {
+        report.error(
+            offset,
+            format!(
+                "The byte rate is {} but {} with {} byte frames is {} bytes a second",
+                header.byte_rate.0, header.sample_rate, block_align, expected_rate
+            ),
+        );
+    }
+
+    let width = match header.sample_width() {
+        Ok(width) => width,
+        Err(e) => return report.header_error(e),
+    };
+    let needed = u64::from(header.num_channels) * u64::from(header.bits_per_sample.div_ceil(8));
+    if block_align != needed {
+        report.error(
+            offset,
+            format!(
+                "The block align is {} but {} channel(s) of {} bit samples only need {}",
+                block_align, header.num_channels, header.bits_per_sample, needed
+            ),
+        );
+    }
+
+    if let Err(e) = header.decode_sample(&vec![0; width]) {
+        report.header_error(e)?;
+    }
+
+    if let Some(data_len) = data_len {
+        let partial = data_len % block_align;
+        if partial != 0 {
+            report.error(
+                header
+                    .find_chunk(header.riff.container.data_id())
+                    .map(|data| data.offset),
+                format!(
+                    "The data isn't a whole number of frames, {} bytes are left over",
+                    partial
+                ),
+            );
+        }
+    }
+    Ok(())
+}
+
+// The block align is a whole block of frames, and the last block is allowed to be short, so
+// these follow their own rules
+fn lint_adpcm_format(header: &WaveHeader, report: &mut Report) -> Result<(), WavReadError> {
+    let offset = header
+        .find_chunk(header.riff.container.format_id())
+        .map(|fmt| fmt.offset);
+    let block_frames = match header.adpcm_block_frames() {
+        Ok(frames) => frames as u64,
+        Err(e) => return report.header_error(e),
+    };
+
+    let block_align = u64::from(header.block_align);
+    let expected_rate = u64::from(header.sample_rate.0) * block_align / block_frames;
+    if u64::from(header.byte_rate.0) != expected_rate {
+        report.error(
+            offset,
+            format!(
+                "The byte rate is {} but {} with {} frames in {} byte blocks is {} bytes a second",
+                header.byte_rate.0, header.sample_rate, block_frames, block_align, expected_rate
+            ),
+        );
+    }
+
+    if header.find_chunk(b"fact").is_none() {
+        report.warning(
+            None,
+            String::from("There's no 'fact' chunk, so padding in the last block will be decoded"),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(contents);
+        if contents.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn riff(chunks: &[Vec<u8>], file_size: Option<u32>) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let size = file_size.unwrap_or(4 + body.len() as u32);
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&size.to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    fn format(byte_rate: u32, block_align: u16, bits: u16) -> Vec<u8> {
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&1_u16.to_le_bytes());
+        fmt.extend_from_slice(&1_u16.to_le_bytes());
+        fmt.extend_from_slice(&8000_u32.to_le_bytes());
+        fmt.extend_from_slice(&byte_rate.to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        chunk(b"fmt ", &fmt)
+    }
+
+    fn lint_bytes(bytes: Vec<u8>) -> Report {
+        lint(&mut std::io::Cursor::new(bytes)).unwrap()
+    }
+
+    fn severities(report: &Report) -> Vec<Severity> {
+        report.findings.iter().map(|f| f.severity).collect()
+    }
+
+    #[test]
+    fn lint_clean_file() {
+        let file = super::super::tests::get_full_path_of_test_resource("test_input.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let report = lint(&mut file).unwrap();
+        assert_eq!(report.findings, []);
+        assert_eq!(report.to_string(), "No problems found");
+    }
+
+    #[test]
+    fn lint_aiff_files() {
+        for name in &["test_input.aiff", "test_input.aifc"] {
+            let file = super::super::tests::get_full_path_of_test_resource(name);
+            let mut file = std::fs::File::open(file).unwrap();
+            assert_eq!(lint(&mut file).unwrap().findings, []);
+        }
+    }
+
+    #[test]
+    fn lint_adpcm_file() {
+        let file = super::super::tests::get_full_path_of_test_resource("test_input_adpcm.wav");
+        let bytes = std::fs::read(file).unwrap();
+        assert_eq!(lint_bytes(bytes.clone()).findings, []);
+
+        let mut bytes = bytes;
+        // A byte rate worked out as if it was PCM, and no 'fact'
+        bytes[28..32].copy_from_slice(&(8000u32 * 16).to_le_bytes());
+        bytes[40..44].copy_from_slice(b"JUNK");
+        let report = lint_bytes(bytes);
+        assert_eq!(severities(&report), [Severity::Error, Severity::Warning]);
+    }
+
+    #[test]
+    fn lint_reports_everything() {
+        let bytes = riff(
+            &[
+                format(1234, 4, 16),
+                chunk(b"JUNK", b"odd"),
+                format(16000, 2, 16),
+                chunk(b"data", &[0; 5]),
+            ],
+            Some(1000),
+        );
+        let report = lint_bytes(bytes);
+        let messages: Vec<&str> = report.findings.iter().map(|f| f.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "There's more than one 'fmt ' chunk",
+                "The RIFF header says the file is 1008 bytes but it's 86",
+                "The byte rate is 1234 but 8000 Hz with 4 byte frames is 32000 bytes a second",
+                "The block align is 4 but 1 channel(s) of 16 bit samples only need 2",
+                "The data isn't a whole number of frames, 1 bytes are left over",
+            ]
+        );
+        assert_eq!(report.errors(), 5);
+    }
+
+    #[test]
+    fn lint_truncated_and_unpadded() {
+        // 8 bit, so that 3 bytes are whole frames
+        let mut bytes = riff(&[format(8000, 1, 8), chunk(b"data", &[0; 3])], None);
+        bytes.pop();
+        let report = lint_bytes(bytes.clone());
+        assert_eq!(report.errors(), 0);
+        assert_eq!(report.findings[0].offset, Some(47));
+        assert_eq!(
+            report.findings[0].message,
+            "The 'data' chunk is missing its pad byte"
+        );
+
+        bytes.truncate(46);
+        let report = lint_bytes(bytes);
+        assert_eq!(report.errors(), 2);
+        assert_eq!(
+            report.findings[0].message,
+            "The 'data' chunk should be 3 bytes but the file only has 2"
+        );
+        assert_eq!(
+            report.findings[1].message,
+            "The RIFF header says the file is 48 bytes but it's 46"
+        );
+    }
+
+    #[test]
+    fn lint_unfinished_file() {
+        // Both sizes were left at 0 by a recorder that never got to fill them in
+        let file = super::super::tests::get_full_path_of_test_resource("test_input_unfinished.wav");
+        let mut file = std::fs::File::open(file).unwrap();
+        let report = lint(&mut file).unwrap();
+        assert_eq!(report.errors(), 1);
+        assert_eq!(
+            report.findings[1].message,
+            "The RIFF header says the file is 8 bytes but it's 51"
+        );
+    }
+
+    #[test]
+    fn lint_bad_header() {
+        let report = lint_bytes(b"RIFZ\0\0\0\0WAVE".to_vec());
+        assert_eq!(severities(&report), [Severity::Error]);
+        assert_eq!(report.findings[0].offset, Some(0));
+
+        let report = lint_bytes(riff(&[format(16000, 2, 20)], None));
+        assert_eq!(severities(&report), [Severity::Error, Severity::Error]);
+        assert!(report.findings[0].message.starts_with("'data' not present"));
+        assert_eq!(
+            report.findings[1].message,
+            "The block align doesn't fit a sample for each channel \
+             ('fmt ' chunk, at byte 12, found 02 00)"
+        );
+    }
+
+    #[test]
+    fn lint_json() {
+        let mut report = Report::default();
+        report.error(Some(12), String::from("Bad \"fmt \""));
+        report.warning(None, String::from("Odd"));
+        assert_eq!(
+            report.to_json(),
+            "{\"errors\":1,\"warnings\":1,\"findings\":[\
+             {\"severity\":\"error\",\"offset\":12,\"message\":\"Bad \\\"fmt \\\"\"},\
+             {\"severity\":\"warning\",\"offset\":null,\"message\":\"Odd\"}]}"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+
+use prettytable::{Row, Table};
+
+use super::{read_chunk_contents, seek_within, ChunkWalker, Endianness, RiffHeader, WavReadError};
+
+// A chunk and everything inside it, for showing how a file is laid out
+#[derive(PartialEq, Debug, Default)]
+pub(super) struct ChunkNode {
+    id: [u8; 4],
+    // What sort of list it is for LIST chunks, and WAVE or AIFF for the file itself
+    form: Option<[u8; 4]>,
+    offset: u64,
+    length: u64,
+    padding: u64,
+    children: Vec<ChunkNode>,
+}
+
+impl ChunkNode {
+    fn add_rows(&self, tbl: &mut Table, depth: usize) {
+        let mut name = "  ".repeat(depth) + &String::from_utf8_lossy(&self.id);
+        if let Some(form) = &self.form {
+            name += &format!(" ({})", String::from_utf8_lossy(form));
+        }
+        tbl.add_row(Row::from(vec![
+            name,
+            self.offset.to_string(),
+            self.length.to_string(),
+            self.padding.to_string(),
+        ]));
+        for child in &self.children {
+            child.add_rows(tbl, depth + 1);
+        }
+    }
+}
+
+impl fmt::Display for ChunkNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut tbl = Table::new();
+        tbl.set_titles(Row::from(vec!["Chunk", "Offset", "Size", "Padding"]));
+        self.add_rows(&mut tbl, 0);
+        write!(f, "{}", tbl)
+    }
+}
+
+// Reads the whole file's layout, carrying on past anything the decoder wouldn't accept so
+// that odd files can still be looked at
+pub(super) fn read_tree<R: Read + Seek>(f: &mut R) -> Result<ChunkNode, WavReadError> {
+    f.seek(SeekFrom::Start(0))?;
+    let mut magic: [u8; 4] = [0; 4];
+    f.read_exact(&mut magic)?;
+    f.seek(SeekFrom::Start(0))?;
+    let mut riff = RiffHeader::from_reader(f)?;
+
+    let mut walker = ChunkWalker::new(riff.container);
+    let mut children = Vec::new();
+    loop {
+        seek_within(f, walker.offset)?;
+        let chunk = match walker.next_chunk(f)? {
+            Some(chunk) => chunk,
+            None => break,
+        };
+        if riff.container.has_ds64() && &chunk.id == b"ds64" {
+            riff.file_size = walker.read_ds64(f, &chunk)?;
+        }
+
+        let mut node = ChunkNode {
+            id: chunk.id,
+            offset: chunk.offset,
+            length: chunk.length,
+            padding: chunk.padded_length() - chunk.length,
+            ..Default::default()
+        };
+        if &chunk.id == b"LIST" {
+            let contents = read_chunk_contents(f, &chunk)?;
+            if contents.len() >= 4 {
+                node.form = Some(contents[..4].try_into().unwrap());
+                node.children = read_subchunks(
+                    &contents[4..],
+                    chunk.data_offset() + 4,
+                    riff.container.endianness(),
+                );
+            }
+        }
+        children.push(node);
+    }
+
+    Ok(ChunkNode {
+        id: magic,
+        form: Some(*riff.container.form_type()),
+        offset: 0,
+        length: riff.file_size,
+        padding: 0,
+        children,
+    })
+}
+
+// The chunks inside a list are always the plain RIFF kind, or RIFX in a RIFX file
+fn read_subchunks(mut contents: &[u8], mut offset: u64, endianness: Endianness) -> Vec<ChunkNode> {
+    let mut nodes = Vec::new();
+    while contents.len() >= 8 {
+        let mut id: [u8; 4] = [0; 4];
+        contents.read_exact(&mut id).unwrap();
+        let mut length: [u8; 4] = [0; 4];
+        contents.read_exact(&mut length).unwrap();
+        // Widened so that the pad byte can't overflow a size of 0xffffffff
+        let length = u64::from(endianness.u32_from_bytes(length));
+        let padded = length + length % 2;
+
+        let mut node = ChunkNode {
+            id,
+            offset,
+            length,
+            padding: length % 2,
+            ..Default::default()
+        };
+        if &id == b"LIST" && contents.len() >= 4 && length >= 4 {
+            node.form = Some(contents[..4].try_into().unwrap());
+            let end = length.min(contents.len() as u64) as usize;
+            node.children = read_subchunks(&contents[4..end], offset + 12, endianness);
+        }
+        nodes.push(node);
+
+        offset += 8 + padded;
+        // A size that runs past the end of the list can't be followed any further
+        if padded > contents.len() as u64 {
+            break;
+        }
+        contents = &contents[padded as usize..];
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree_of(name: &str) -> ChunkNode {
+        let file = super::super::tests::get_full_path_of_test_resource(name);
+        read_tree(&mut std::fs::File::open(file).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn tree_of_plain_file() {
+        let tree = tree_of("test_input.wav");
+        assert_eq!(&tree.id, b"RIFF");
+        assert_eq!(tree.length, 636_998);
+        let ids: Vec<&[u8; 4]> = tree.children.iter().map(|c| &c.id).collect();
+        assert_eq!(ids, [b"fmt ", b"LIST", b"data"]);
+
+        let list = &tree.children[1];
+        assert_eq!(list.form, Some(*b"INFO"));
+        assert_eq!(
+            list.children,
+            [ChunkNode {
+                id: *b"ISFT",
+                offset: 48,
+                length: 14,
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn tree_shows_padding() {
+        let tree = tree_of("test_input_chunk_order.wav");
+        let junk = &tree.children[0];
+        assert_eq!(&junk.id, b"JUNK");
+        assert_eq!((junk.length, junk.padding), (3, 1));
+
+        let tree = tree_of("test_input_info.wav");
+        let list = tree.children.iter().find(|c| &c.id == b"LIST").unwrap();
+        let paddings: Vec<u64> = list.children.iter().map(|c| c.padding).collect();
+        assert_eq!(paddings, [1, 0, 0, 1]);
+        assert_eq!(list.children[3].offset, 102);
+    }
+
+    #[test]
+    fn tree_of_oversized_entry() {
+        let mut contents = b"INAM".to_vec();
+        contents.extend_from_slice(&u32::MAX.to_le_bytes());
+        contents.extend_from_slice(b"abc");
+        let nodes = read_subchunks(&contents, 12, Endianness::Little);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(
+            (nodes[0].length, nodes[0].padding),
+            (u64::from(u32::MAX), 1)
+        );
+    }
+
+    #[test]
+    fn tree_of_rifx_file() {
+        let tree = tree_of("test_input_rifx.wav");
+        assert_eq!(&tree.id, b"RIFX");
+        let list = &tree.children[1];
+        assert_eq!((list.length, list.children[0].length), (18, 5));
+        assert_eq!(list.children[0].padding, 1);
+    }
+
+    #[test]
+    fn tree_of_aiff_file() {
+        let tree = tree_of("test_input.aifc");
+        assert_eq!((&tree.id, tree.form), (b"FORM", Some(*b"AIFC")));
+        let ids: Vec<&[u8; 4]> = tree.children.iter().map(|c| &c.id).collect();
+        assert_eq!(ids, [b"FVER", b"COMM", b"SSND"]);
+    }
+
+    #[test]
+    fn tree_table() {
+        let table = tree_of("test_input.wav").to_string();
+        assert!(table.contains("| RIFF (WAVE)"));
+        assert!(table.contains("|   LIST (INFO)"));
+        assert!(table.contains("|     ISFT"));
+    }
+}
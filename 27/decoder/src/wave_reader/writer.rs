@@ -0,0 +1,756 @@
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use super::{ChannelSelection, Chunk, Container, Hertz, WaveFile, WaveFormat, WaveHeader};
+
+#[derive(Debug)]
+pub(super) enum WavWriteError {
+    FileIO(std::io::Error),
+    UnsupportedFormat,
+    ChannelLengths,
+    UnsupportedContainer,
+    TooLarge,
+}
+
+impl fmt::Display for WavWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let specific: &'static str = match self {
+            WavWriteError::FileIO(_) => "FileIO error and unable to write to the file",
+            WavWriteError::UnsupportedFormat => {
+                "Only 8, 16, 24 or 32 bit PCM and 32 or 64 bit float can be written"
+            }
+            WavWriteError::ChannelLengths => "Every channel needs the same number of samples",
+            WavWriteError::UnsupportedContainer => "Labels can only be added to RIFF WAV files",
+            WavWriteError::TooLarge => {
+                "The file or its labels would be too big for the sizes and positions in its header"
+            }
+        };
+        write!(f, "Error writing WAV file: {}", specific)
+    }
+}
+
+impl Error for WavWriteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WavWriteError::FileIO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::convert::From<std::io::Error> for WavWriteError {
+    fn from(value: std::io::Error) -> Self {
+        WavWriteError::FileIO(value)
+    }
+}
+
+// Sizes, counts and cue points are all 32 bit, so anything bigger is refused rather than
+// cut short
+fn u32_field<T: TryInto<u32>>(value: T) -> Result<u32, WavWriteError> {
+    value.try_into().map_err(|_| WavWriteError::TooLarge)
+}
+
+impl WaveHeader {
+    // The rates and alignment follow from the format, so they're worked out here rather than
+    // being passed in and possibly disagreeing with each other
+    pub(super) fn new(
+        audio_format: WaveFormat,
+        num_channels: u16,
+        sample_rate: u32,
+        bits_per_sample: u16,
+    ) -> Self {
+        let block_align = num_channels * bits_per_sample.div_ceil(8);
+        WaveHeader {
+            audio_format,
+            num_channels,
+            sample_rate: Hertz(sample_rate),
+            byte_rate: Hertz(sample_rate * u32::from(block_align)),
+            block_align,
+            bits_per_sample,
+            ..Default::default()
+        }
+    }
+
+    fn encode_sample<W: Write>(&self, w: &mut W, sample: f32) -> Result<(), WavWriteError> {
+        // Work in f64 so that 32 bit samples keep all their precision
+        let sample = f64::from(sample.clamp(-1.0, 1.0));
+        match (&self.audio_format, self.bits_per_sample) {
+            (WaveFormat::Pcm, 8) => w.write_u8((sample * 128.0 + 128.0).round().min(255.0) as u8)?,
+            (WaveFormat::Pcm, 16) => w.write_i16::<LittleEndian>(scale(sample, 15) as i16)?,
+            (WaveFormat::Pcm, 24) => w.write_i24::<LittleEndian>(scale(sample, 23) as i32)?,
+            (WaveFormat::Pcm, 32) => w.write_i32::<LittleEndian>(scale(sample, 31) as i32)?,
+            (WaveFormat::Float, 32) => w.write_f32::<LittleEndian>(sample as f32)?,
+            (WaveFormat::Float, 64) => w.write_f64::<LittleEndian>(sample)?,
+            _ => return Err(WavWriteError::UnsupportedFormat),
+        }
+        Ok(())
+    }
+}
+
+// Full scale positive can't be represented, so it's clipped to the largest value there is
+fn scale(sample: f64, bits: i32) -> f64 {
+    let full_scale = 2.0_f64.powi(bits);
+    (sample * full_scale).round().min(full_scale - 1.0)
+}
+
+fn write_chunk_header<W: Write>(w: &mut W, id: &[u8; 4], length: u32) -> std::io::Result<()> {
+    w.write_all(id)?;
+    w.write_u32::<LittleEndian>(length)
+}
+
+// The entries are nul terminated and padded to an even length, same as read_info_list expects
+fn info_list(
+    metadata: &std::collections::BTreeMap<String, String>,
+) -> Result<Vec<u8>, WavWriteError> {
+    let mut list = b"INFO".to_vec();
+    for (id, value) in metadata {
+        let mut id_bytes = [b' '; 4];
+        for (dst, src) in id_bytes.iter_mut().zip(id.bytes()) {
+            *dst = src;
+        }
+        list.extend_from_slice(&id_bytes);
+        let length = value.len() + 1;
+        list.extend_from_slice(&u32_field(length)?.to_le_bytes());
+        list.extend_from_slice(value.as_bytes());
+        list.push(0);
+        if !length.is_multiple_of(2) {
+            list.push(0);
+        }
+    }
+    Ok(list)
+}
+
+impl WaveFile {
+    pub(super) fn new(header: WaveHeader, channels: Vec<Vec<f32>>) -> Self {
+        WaveFile { header, channels }
+    }
+
+    // The same samples in a format that can be written. Sizes the writer can't produce, and
+    // formats such as mu-law or ADPCM, come out as 16 bit PCM. Mixing down is the only
+    // channel selection that applies, a single channel is still decoded from the whole copy
+    pub(super) fn converted(
+        self,
+        channel: &ChannelSelection,
+        float: bool,
+    ) -> Result<Self, WavWriteError> {
+        let (format, bits) = match (self.header.sample_format(), self.header.bits_per_sample) {
+            _ if float => (WaveFormat::Float, 32),
+            (WaveFormat::Pcm, bits @ (8 | 16 | 24 | 32)) => (WaveFormat::Pcm, bits),
+            (WaveFormat::Float, bits @ (32 | 64)) => (WaveFormat::Float, bits),
+            _ => (WaveFormat::Pcm, 16),
+        };
+        let sample_rate = self.header.sample_rate.0;
+        let (num_channels, channels) = match channel {
+            ChannelSelection::Mix => (1, vec![self.mono()]),
+            ChannelSelection::Single(_) => (self.header.num_channels, self.channels),
+        };
+        // Wider samples than the file had can take the block align or byte rate past their
+        // fields
+        num_channels
+            .checked_mul(bits / 8)
+            .and_then(|block_align| sample_rate.checked_mul(block_align.into()))
+            .ok_or(WavWriteError::TooLarge)?;
+
+        let mut header = WaveHeader::new(format, num_channels, sample_rate, bits);
+        header.metadata = self.header.metadata;
+        Ok(WaveFile::new(header, channels))
+    }
+
+    // Writes a complete file with 'fmt ', an optional LIST/INFO and 'data'. Float files also
+    // get the 'fact' chunk that the spec asks for with anything that isn't PCM
+    pub(super) fn write<W: Write>(&self, w: &mut W) -> Result<(), WavWriteError> {
+        let header = &self.header;
+        let frames = self.channels.first().map_or(0, Vec::len);
+        if self.channels.len() != usize::from(header.num_channels)
+            || self.channels.iter().any(|c| c.len() != frames)
+        {
+            return Err(WavWriteError::ChannelLengths);
+        }
+        // Extensible files are written out as whatever format their samples are in
+        let format_tag: u16 = match header.sample_format() {
+            WaveFormat::Pcm => 1,
+            WaveFormat::Float => 3,
+            _ => return Err(WavWriteError::UnsupportedFormat),
+        };
+        // Whatever the header says, the rates and alignment written out have to match the samples
+        let layout = WaveHeader::new(
+            WaveFormat::try_from(format_tag).unwrap(),
+            header.num_channels,
+            header.sample_rate.0,
+            header.bits_per_sample,
+        );
+
+        let is_float = layout.audio_format == WaveFormat::Float;
+        let fmt_len: u32 = if is_float { 18 } else { 16 };
+        let list = if header.metadata.is_empty() {
+            None
+        } else {
+            Some(info_list(&header.metadata)?)
+        };
+
+        // Everything is added up in 64 bits so that anything too big is found rather than
+        // wrapping round
+        let data_len = frames as u64 * u64::from(layout.block_align);
+        let mut file_size = 4 + 8 + u64::from(fmt_len) + 8 + data_len + data_len % 2;
+        if is_float {
+            file_size += 8 + 4;
+        }
+        if let Some(list) = &list {
+            file_size += 8 + list.len() as u64;
+        }
+        let file_size = u32_field(file_size)?;
+        let data_len = u32_field(data_len)?;
+
+        w.write_all(b"RIFF")?;
+        w.write_u32::<LittleEndian>(file_size)?;
+        w.write_all(b"WAVE")?;
+
+        write_chunk_header(w, b"fmt ", fmt_len)?;
+        w.write_u16::<LittleEndian>(format_tag)?;
+        w.write_u16::<LittleEndian>(layout.num_channels)?;
+        w.write_u32::<LittleEndian>(layout.sample_rate.0)?;
+        w.write_u32::<LittleEndian>(layout.byte_rate.0)?;
+        w.write_u16::<LittleEndian>(layout.block_align)?;
+        w.write_u16::<LittleEndian>(layout.bits_per_sample)?;
+        if is_float {
+            // No extension, but the size of it still has to be there
+            w.write_u16::<LittleEndian>(0)?;
+            write_chunk_header(w, b"fact", 4)?;
+            w.write_u32::<LittleEndian>(u32_field(frames)?)?;
+        }
+
+        if let Some(list) = &list {
+            write_chunk_header(w, b"LIST", u32_field(list.len())?)?;
+            w.write_all(list)?;
+        }
+
+        write_chunk_header(w, b"data", data_len)?;
+        {
+            let mut data = std::io::BufWriter::new(w.by_ref());
+            for i in 0..frames {
+                for channel in &self.channels {
+                    layout.encode_sample(&mut data, channel[i])?;
+                }
+            }
+            data.flush()?;
+        }
+        if !data_len.is_multiple_of(2) {
+            w.write_u8(0)?;
+        }
+        Ok(())
+    }
+}
+
+// Each cue point is given the same id as the label that goes with it, counting from 1
+fn cue_chunk(labels: &[(u64, String)]) -> Result<Vec<u8>, WavWriteError> {
+    let mut cue = u32_field(labels.len())?.to_le_bytes().to_vec();
+    for (id, (frame, _)) in (1u32..).zip(labels) {
+        let frame = u32_field(*frame)?;
+        cue.extend_from_slice(&id.to_le_bytes());
+        cue.extend_from_slice(&frame.to_le_bytes());
+        // Which chunk the point is in and where, with the offset counted in frames
+        cue.extend_from_slice(b"data");
+        cue.extend_from_slice(&[0; 8]);
+        cue.extend_from_slice(&frame.to_le_bytes());
+    }
+    Ok(cue)
+}
+
+fn adtl_list(labels: &[(u64, String)]) -> Result<Vec<u8>, WavWriteError> {
+    let mut list = b"adtl".to_vec();
+    for (id, (_, text)) in (1u32..).zip(labels) {
+        let length = 4 + text.len() + 1;
+        list.extend_from_slice(b"labl");
+        list.extend_from_slice(&u32_field(length)?.to_le_bytes());
+        list.extend_from_slice(&id.to_le_bytes());
+        list.extend_from_slice(text.as_bytes());
+        list.push(0);
+        if !length.is_multiple_of(2) {
+            list.push(0);
+        }
+    }
+    Ok(list)
+}
+
+// Labels from an earlier run are replaced rather than added to
+fn is_label_chunk<R: Read + Seek>(f: &mut R, chunk: &Chunk) -> std::io::Result<bool> {
+    match &chunk.id {
+        b"cue " => Ok(true),
+        b"LIST" => {
+            let mut list_type = [0; 4];
+            f.seek(SeekFrom::Start(chunk.data_offset()))?;
+            f.read_exact(&mut list_type)?;
+            Ok(&list_type == b"adtl")
+        }
+        _ => Ok(false),
+    }
+}
+
+// The 'data' GUID in Wave64 files, for when a chunk is renamed to it. The ids of the others
+// are copied from the file
+const WAVE64_DATA_GUID: [u8; 16] = [
+    b'd', b'a', b't', b'a', 0xf3, 0xac, 0xd3, 0x11, 0x8c, 0xd1, 0x00, 0xc0, 0x4f, 0x8e, 0xdb, 0x8a,
+];
+
+impl WaveHeader {
+    // How much of a chunk is really there, as the last one can run past the end of the file
+    fn length_in_file(chunk: &Chunk, file_len: u64) -> u64 {
+        chunk
+            .length
+            .min(file_len.saturating_sub(chunk.data_offset()))
+    }
+
+    // Copies the given chunks byte for byte from the file this header was read from, each with
+    // the length it's given, then adds the new chunks on the end. Only the ids and sizes in
+    // the chunk headers, and the sizes in ds64, are written afresh
+    fn copy_chunks<R: Read + Seek, W: Write>(
+        &self,
+        f: &mut R,
+        chunks: &[(&Chunk, u64)],
+        new: &[(&[u8; 4], &[u8])],
+        w: &mut W,
+    ) -> Result<(), WavWriteError> {
+        let container = self.riff.container;
+        let endianness = container.endianness();
+        let alignment = container.alignment();
+        let padded = |length: u64| length.div_ceil(alignment) * alignment;
+
+        let mut file_len = container.file_header_len();
+        for (_, length) in chunks {
+            file_len += container.chunk_header_len() + padded(*length);
+        }
+        for (_, contents) in new {
+            file_len += 8 + padded(contents.len() as u64);
+        }
+        let data_len = chunks
+            .iter()
+            .find(|(chunk, _)| &chunk.id == container.data_id())
+            .map_or(0, |(_, length)| *length);
+        let has_ds64 = container.has_ds64() && chunks.iter().any(|(chunk, _)| &chunk.id == b"ds64");
+        let size_field = |length: u64| match u32::try_from(length) {
+            Ok(length) => Ok(endianness.u32_to_bytes(length)),
+            // The real size is in ds64
+            Err(_) if has_ds64 => Ok([0xff; 4]),
+            Err(_) => Err(WavWriteError::TooLarge),
+        };
+
+        let mut file_header = vec![0; container.file_header_len() as usize];
+        f.seek(SeekFrom::Start(0))?;
+        f.read_exact(&mut file_header)?;
+        match container {
+            Container::Wave64 => file_header[16..24].copy_from_slice(&file_len.to_le_bytes()),
+            _ if has_ds64 => file_header[4..8].copy_from_slice(&[0xff; 4]),
+            _ => file_header[4..8].copy_from_slice(&size_field(file_len - 8)?),
+        }
+        w.write_all(&file_header)?;
+
+        for (chunk, length) in chunks {
+            let mut chunk_header = vec![0; container.chunk_header_len() as usize];
+            f.seek(SeekFrom::Start(chunk.offset))?;
+            f.read_exact(&mut chunk_header)?;
+            match container {
+                Container::Wave64 => {
+                    // Anything else keeps its GUID, which for lists isn't the same as its id
+                    if &chunk.id == b"data" {
+                        chunk_header[..16].copy_from_slice(&WAVE64_DATA_GUID);
+                    }
+                    let size = length + container.chunk_header_len();
+                    chunk_header[16..24].copy_from_slice(&size.to_le_bytes());
+                }
+                _ => {
+                    chunk_header[..4].copy_from_slice(&chunk.id);
+                    // Sizes that are in ds64 stay there
+                    let in_ds64 = &chunk.id == b"data" || chunk_header[4..8] == [0xff; 4];
+                    let size = match in_ds64 && has_ds64 {
+                        true => [0xff; 4],
+                        false => size_field(*length)?,
+                    };
+                    chunk_header[4..8].copy_from_slice(&size);
+                }
+            }
+            w.write_all(&chunk_header)?;
+
+            f.seek(SeekFrom::Start(chunk.data_offset()))?;
+            let mut contents = f.by_ref().take(*length);
+            if has_ds64 && &chunk.id == b"ds64" && *length >= 16 {
+                // The RIFF and data sizes come first, everything after them is kept
+                w.write_u64::<LittleEndian>(file_len - 8)?;
+                w.write_u64::<LittleEndian>(data_len)?;
+                contents.read_exact(&mut [0; 16])?;
+            }
+            std::io::copy(&mut contents, w)?;
+            if contents.limit() != 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            w.write_all(&vec![0; (padded(*length) - length) as usize])?;
+        }
+
+        for (id, contents) in new {
+            let length = contents.len() as u64;
+            w.write_all(*id)?;
+            w.write_all(&size_field(length)?)?;
+            w.write_all(contents)?;
+            w.write_all(&vec![0; (padded(length) - length) as usize])?;
+        }
+        Ok(())
+    }
+
+    // Copies the file this header was recovered from with the sizes it should have had. The
+    // samples are left as they are, less any partial frame on the end
+    pub(super) fn write_repaired<R: Read + Seek, W: Write>(
+        &self,
+        f: &mut R,
+        w: &mut W,
+    ) -> Result<(), WavWriteError> {
+        let file_len = f.seek(SeekFrom::End(0))?;
+        let data_id = self.riff.container.data_id();
+        let chunks: Vec<(&Chunk, u64)> = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let length = WaveHeader::length_in_file(chunk, file_len);
+                // ADPCM blocks can be cut short and still decode
+                if &chunk.id != data_id
+                    || self.block_align == 0
+                    || self.audio_format == WaveFormat::ImaAdpcm
+                {
+                    return (chunk, length);
+                }
+                // Anything before the samples, such as the offset at the start of 'SSND'
+                let before = self.data_offset.saturating_sub(chunk.data_offset());
+                let samples = length.saturating_sub(before);
+                (
+                    chunk,
+                    before + samples - samples % u64::from(self.block_align),
+                )
+            })
+            .collect();
+        self.copy_chunks(f, &chunks, &[], w)
+    }
+
+    // Copies the file this header was read from with a cue point and label for each of the
+    // (frame, text) pairs on the end. The chunks are copied as they are, so nothing is lost
+    // even in formats that can't be decoded and written back out
+    pub(super) fn write_labelled<R: Read + Seek, W: Write>(
+        &self,
+        f: &mut R,
+        labels: &[(u64, String)],
+        w: &mut W,
+    ) -> Result<(), WavWriteError> {
+        if self.riff.container != Container::Riff {
+            return Err(WavWriteError::UnsupportedContainer);
+        }
+        let file_len = f.seek(SeekFrom::End(0))?;
+        let mut chunks = Vec::new();
+        for chunk in &self.chunks {
+            if !is_label_chunk(f, chunk)? {
+                chunks.push((chunk, WaveHeader::length_in_file(chunk, file_len)));
+            }
+        }
+        let cue = cue_chunk(labels)?;
+        let list = adtl_list(labels)?;
+        self.copy_chunks(f, &chunks, &[(b"cue ", &cue), (b"LIST", &list)], w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(format: WaveFormat, bits: u16, channels: Vec<Vec<f32>>) -> WaveFile {
+        let header = WaveHeader::new(format, channels.len() as u16, 8000, bits);
+        let mut bytes = Vec::new();
+        WaveFile::new(header, channels).write(&mut bytes).unwrap();
+        WaveFile::from_reader(&mut std::io::Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn write_pcm_depths() {
+        let samples = vec![0.0, 0.5, -1.0, -0.25];
+        for bits in &[8, 16, 24, 32] {
+            let wave = round_trip(WaveFormat::Pcm, *bits, vec![samples.clone()]);
+            assert_eq!(wave.header.bits_per_sample, *bits);
+            assert_eq!(wave.header.block_align, bits / 8);
+            assert_eq!(wave.header.byte_rate, Hertz(8000 * u32::from(bits / 8)));
+            assert_eq!(wave.channels[0], samples);
+        }
+    }
+
+    #[test]
+    fn write_float_stereo() {
+        let left = vec![0.0, 0.1, -0.3];
+        let right = vec![1.0, -0.7, 0.2];
+        for bits in &[32, 64] {
+            let wave = round_trip(WaveFormat::Float, *bits, vec![left.clone(), right.clone()]);
+            assert_eq!(wave.header.audio_format, WaveFormat::Float);
+            assert_eq!(wave.header.block_align, 2 * bits / 8);
+            assert_eq!(wave.channels, [left.clone(), right.clone()]);
+            assert!(wave.header.find_chunk(b"fact").is_some());
+        }
+    }
+
+    #[test]
+    fn write_clips_full_scale() {
+        let wave = round_trip(WaveFormat::Pcm, 16, vec![vec![1.0, 2.0, -2.0]]);
+        assert_eq!(
+            wave.channels[0],
+            [32767.0 / 32768.0, 32767.0 / 32768.0, -1.0]
+        );
+    }
+
+    #[test]
+    fn write_sizes_and_padding() {
+        let mut header = WaveHeader::new(WaveFormat::Pcm, 1, 8000, 8);
+        header
+            .metadata
+            .insert(String::from("INAM"), String::from("odd"));
+        let mut bytes = Vec::new();
+        WaveFile::new(header, vec![vec![0.0; 3]])
+            .write(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes.len() % 2, 0);
+
+        let header = WaveHeader::from_reader(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(header.riff.file_size as usize, bytes.len() - 8);
+        assert_eq!(header.metadata["INAM"], "odd");
+        assert_eq!(header.find_chunk(b"data").unwrap().length, 3);
+    }
+
+    fn labelled(bytes: &[u8], labels: &[(u64, String)]) -> Result<Vec<u8>, WavWriteError> {
+        let mut f = std::io::Cursor::new(bytes);
+        let header = WaveHeader::from_reader(&mut f).unwrap();
+        let mut out = Vec::new();
+        header.write_labelled(&mut f, labels, &mut out)?;
+        Ok(out)
+    }
+
+    // The last one, as that's where the labels go
+    fn chunk_contents(bytes: &[u8], id: &[u8; 4]) -> Vec<u8> {
+        let mut f = std::io::Cursor::new(bytes);
+        let header = WaveHeader::from_reader(&mut f).unwrap();
+        let chunk = header.chunks.iter().rev().find(|c| &c.id == id).unwrap();
+        let start = chunk.data_offset() as usize;
+        bytes[start..start + chunk.length as usize].to_vec()
+    }
+
+    // Sizes of 0, as left by a recorder that crashed before it could fill them in
+    fn crash(bytes: &[u8]) -> Vec<u8> {
+        let header = WaveHeader::from_reader(&mut std::io::Cursor::new(bytes)).unwrap();
+        let container = header.riff.container;
+        let data = header.find_chunk(container.data_id()).unwrap().offset as usize;
+        let mut crashed = bytes.to_vec();
+        match container {
+            Container::Wave64 => {
+                crashed[16..24].copy_from_slice(&[0; 8]);
+                crashed[data + 16..data + 24].copy_from_slice(&[0; 8]);
+            }
+            // Both the RIFF and data sizes are in ds64
+            Container::Rf64 => {
+                let ds64 = header.find_chunk(b"ds64").unwrap().data_offset() as usize;
+                crashed[ds64..ds64 + 16].copy_from_slice(&[0; 16]);
+            }
+            _ => {
+                crashed[4..8].copy_from_slice(&[0; 4]);
+                crashed[data + 4..data + 8].copy_from_slice(&[0; 4]);
+            }
+        }
+        crashed
+    }
+
+    fn repaired(bytes: &[u8]) -> Vec<u8> {
+        let mut f = std::io::Cursor::new(bytes);
+        let header = WaveHeader::recover(&mut f).unwrap();
+        let mut out = Vec::new();
+        header.write_repaired(&mut f, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn repair_every_container() {
+        let names = [
+            "test_input_32bit.wav",
+            "test_input_adpcm.wav",
+            "test_input_rifx.wav",
+            "test_input_rf64.wav",
+            "test_input.w64",
+            "test_input.aiff",
+            "test_input.aifc",
+        ];
+        for name in &names {
+            let file = super::super::tests::get_full_path_of_test_resource(name);
+            let original = std::fs::read(file).unwrap();
+            let crashed = crash(&original);
+            assert_ne!(crashed, original);
+            assert_eq!(repaired(&crashed), original, "{}", name);
+        }
+
+        // This one's 'data' is missing its pad byte, which the repaired copy gets
+        let file = super::super::tests::get_full_path_of_test_resource("test_input_mulaw.wav");
+        let mut original = std::fs::read(file).unwrap();
+        let repaired = repaired(&crash(&original));
+        original.push(0);
+        original[4] += 1;
+        assert_eq!(repaired, original);
+    }
+
+    #[test]
+    fn repair_keeps_samples_and_chunks() {
+        let file = super::super::tests::get_full_path_of_test_resource("test_input_32bit.wav");
+        let mut original = std::fs::read(file).unwrap();
+        let header = WaveHeader::from_reader(&mut std::io::Cursor::new(&original)).unwrap();
+        let data = header.data_offset as usize;
+        // A sample that doesn't survive being turned into an f32 and back
+        original[data..data + 4].copy_from_slice(&0x1234_5679_u32.to_le_bytes());
+        // Another chunk after 'fmt ' that the decoder has no use for
+        let mut with_chunk = original[..data - 8].to_vec();
+        with_chunk.extend_from_slice(b"abcd\x03\0\0\0xyz\0");
+        with_chunk.extend_from_slice(&original[data - 8..]);
+        let riff_size = with_chunk.len() as u32 - 8;
+        with_chunk[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+        let mut crashed = crash(&with_chunk);
+        // Half a frame on the end is left off
+        crashed.extend_from_slice(&[1, 2]);
+        assert_eq!(repaired(&crashed), with_chunk);
+    }
+
+    #[test]
+    fn write_labels() {
+        let file = super::super::tests::get_full_path_of_test_resource("test_input.wav");
+        let original = std::fs::read(file).unwrap();
+        let labels = [(2073, String::from("hello!")), (39513, String::from("if"))];
+        let bytes = labelled(&original, &labels).unwrap();
+
+        let header = WaveHeader::from_reader(&mut std::io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(header.riff.file_size as usize, bytes.len() - 8);
+        assert_eq!(
+            chunk_contents(&bytes, b"data"),
+            chunk_contents(&original, b"data")
+        );
+
+        let cue = chunk_contents(&bytes, b"cue ");
+        assert_eq!(cue.len(), 4 + 2 * 24);
+        assert_eq!(cue[..4], 2u32.to_le_bytes());
+        assert_eq!(cue[28..32], 2u32.to_le_bytes());
+        assert_eq!(cue[32..36], 39513u32.to_le_bytes());
+        assert_eq!(&cue[36..40], b"data");
+        assert_eq!(cue[48..52], 39513u32.to_le_bytes());
+
+        // The odd length "hello!" gets padded, "if" doesn't need to be
+        let list = chunk_contents(&bytes, b"LIST");
+        let mut expected = b"adtllabl".to_vec();
+        expected.extend_from_slice(&11u32.to_le_bytes());
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(b"hello!\0\0labl");
+        expected.extend_from_slice(&7u32.to_le_bytes());
+        expected.extend_from_slice(&2u32.to_le_bytes());
+        expected.extend_from_slice(b"if\0\0");
+        assert_eq!(list, expected);
+
+        // The existing LIST/INFO is kept, and labelling it again replaces the labels rather
+        // than adding more
+        assert_eq!(
+            header.metadata,
+            WaveHeader::from_reader(&mut std::io::Cursor::new(&original))
+                .unwrap()
+                .metadata
+        );
+        let again = labelled(&bytes, &labels[..1]).unwrap();
+        let header = WaveHeader::from_reader(&mut std::io::Cursor::new(&again)).unwrap();
+        let ids: Vec<&[u8; 4]> = header.chunks.iter().map(|chunk| &chunk.id).collect();
+        assert_eq!(ids.iter().filter(|id| **id == b"cue ").count(), 1);
+        assert_eq!(ids.iter().filter(|id| **id == b"LIST").count(), 2);
+        assert_eq!(chunk_contents(&again, b"cue ")[..4], 1u32.to_le_bytes());
+    }
+
+    #[test]
+    fn write_labels_needs_riff() {
+        let file = super::super::tests::get_full_path_of_test_resource("test_input_rifx.wav");
+        let original = std::fs::read(file).unwrap();
+        assert!(matches!(
+            labelled(&original, &[]),
+            Err(WavWriteError::UnsupportedContainer)
+        ));
+    }
+
+    fn read_fixture(name: &str) -> WaveFile {
+        let file = super::super::tests::get_full_path_of_test_resource(name);
+        WaveFile::from_reader(&mut std::fs::File::open(file).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn convert_keeps_pcm() {
+        let wave = read_fixture("test_input_24bit.wav");
+        let samples = wave.channels.clone();
+        let converted = wave.converted(&ChannelSelection::Single(0), false).unwrap();
+        let mut bytes = Vec::new();
+        converted.write(&mut bytes).unwrap();
+        let read_back = WaveFile::from_reader(&mut std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(read_back.header.bits_per_sample, 24);
+        assert_eq!(read_back.channels, samples);
+    }
+
+    #[test]
+    fn convert_what_cant_be_written() {
+        let converted = read_fixture("test_input_adpcm.wav")
+            .converted(&ChannelSelection::Single(0), false)
+            .unwrap();
+        assert_eq!(converted.header.audio_format, WaveFormat::Pcm);
+        assert_eq!(converted.header.bits_per_sample, 16);
+        assert_eq!(
+            converted.header.block_align,
+            2 * converted.header.num_channels
+        );
+
+        let wave = read_fixture("test_input_3ch.wav");
+        let mono = wave.mono();
+        let converted = wave.converted(&ChannelSelection::Mix, true).unwrap();
+        assert_eq!(converted.header.audio_format, WaveFormat::Float);
+        assert_eq!(converted.header.num_channels, 1);
+        assert_eq!(converted.channels, [mono]);
+
+        // 16 bit samples fit 20000 channels in a block, 32 bit floats don't
+        let header = WaveHeader::new(WaveFormat::Pcm, 20000, 8000, 16);
+        let wave = WaveFile::new(header, vec![Vec::new(); 20000]);
+        assert!(matches!(
+            wave.converted(&ChannelSelection::Single(0), true),
+            Err(WavWriteError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn write_labels_past_32_bits() {
+        let file = super::super::tests::get_full_path_of_test_resource("test_input.wav");
+        let original = std::fs::read(file).unwrap();
+        let labels = [(1 << 32, String::from("e"))];
+        assert!(matches!(
+            labelled(&original, &labels),
+            Err(WavWriteError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn write_keeps_io_error() {
+        let header = WaveHeader::new(WaveFormat::Pcm, 1, 8000, 16);
+        let wave = WaveFile::new(header, vec![vec![0.0; 100]]);
+        // Too small for even the header
+        let mut buffer = [0; 10];
+        let e = wave.write(&mut &mut buffer[..]).err().unwrap();
+        let source = e.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(source.unwrap().kind(), std::io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn write_mismatched_channels() {
+        let header = WaveHeader::new(WaveFormat::Pcm, 2, 8000, 16);
+        let wave = WaveFile::new(header, vec![vec![0.0; 3], vec![0.0; 2]]);
+        assert!(matches!(
+            wave.write(&mut Vec::new()),
+            Err(WavWriteError::ChannelLengths)
+        ));
+    }
+}
@@ -0,0 +1,19 @@
+use std::process::Command;
+
+fn lint(fixture: &str) -> std::process::ExitStatus {
+    Command::new(env!("CARGO_BIN_EXE_decoder"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["lint", fixture])
+        .output()
+        .unwrap()
+        .status
+}
+
+#[test]
+fn lint_exit_code() {
+    assert!(lint("test_input.wav").success());
+    // The RIFF and data sizes were never filled in
+    assert_eq!(lint("test_input_unfinished.wav").code(), Some(1));
+    // A pad byte missing from the end is only a warning
+    assert!(lint("test_input_mulaw.wav").success());
+}
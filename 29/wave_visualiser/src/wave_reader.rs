@@ -1,3 +1,6 @@
+// The visualiser's own copy of the reader the decoder in 27/decoder started from. It only reads
+// 16 bit PCM RIFF/WAVE files. The containers and formats the decoder has learnt since haven't
+// been brought across, so there are files the decoder reads that this can't plot
 use std::convert::Into;
 use std::convert::TryFrom;
 use std::convert::TryInto;